@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS "check_results" (
+    "id" BIGSERIAL PRIMARY KEY,
+    "url" TEXT NOT NULL,
+    "checked_at" TIMESTAMP WITH TIME ZONE NOT NULL,
+    "status" VARCHAR(20) NOT NULL,
+    "ip" INET,
+    "duration" DOUBLE PRECISION NOT NULL,
+    "error" VARCHAR(20)
+);
+CREATE INDEX "check_results_url_checked_at_index" ON "check_results" ("url", "checked_at");
@@ -1,54 +1,332 @@
 //! Contains service logic related to `HomoService`.
 
 use crate::{
+    config::StatsWindow,
     domain::{
-        HomoService, HomoServiceResponse, HomoServiceStatus, HttpResponse, Provider,
+        DualStackResponse, HomoService, HomoServiceError, HomoServiceErrorKind,
+        HomoServiceResponse, HomoServiceStatus, IpVersion, Provider, RedirectError,
         UnwrapOrWarnExt,
     },
-    repository::{AvatarRepository, Repositories},
-    service::{AvatarService, HomoRequestService, Services},
-    validation::response::{ResponseHeaderValidator, ResponseHtmlValidator, ValidateResponseExt},
+    repository::{
+        AvatarRepository, CachedCheckResult, CheckHistoryRepository, CheckRecord,
+        CheckResultRepository, CheckSummary, Repositories, RepositoryError,
+    },
+    service::{AvatarService, HomoRequestService, ServiceError, Services, SingleFlight},
+    validation::is_same_site,
     Container,
 };
-use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
 use lazy_static::lazy_static;
 use log::{info, warn};
 use regex::Regex;
 use serde_json::Value as JsonValue;
-use tokio::sync::broadcast::{channel, Receiver, Sender};
+use tokio::{
+    join, spawn,
+    sync::broadcast::{channel, Receiver, Sender},
+    time::delay_for,
+};
 use url::Url;
 
+/// Checks in flight, keyed by the service URL.
+pub type CheckFlights = SingleFlight<Url, HomoServiceResponse>;
+
 pub type AvatarResolverAttached = (
     Vec<(HomoService, Receiver<Option<Url>>)>,
     HashMap<Provider, Sender<Option<Url>>>,
 );
 
 /// Requests to the service and validates its response whether contains appropriate link(s).
+/// Failures are reported as `HomoServiceStatus::Error` with `HomoServiceError`,
+/// after retries by `RetryConfig`. Destinations refused by `EgressPolicy` are reported as
+/// `HomoServiceStatus::Blocked`.
+/// In dual-stack mode, the service is checked over IPv4 and IPv6 separately.
+/// Concurrent checks of the same service share one request.
+/// The result is cached for `CacheConfig::ttl` and recorded in the history in the background,
+/// so they may not be visible yet when this returns.
 pub async fn request_service(
     deps: impl Container + 'static,
     service_url: Url,
-) -> Result<HomoServiceResponse, Box<dyn Error + Send + Sync>> {
-    let (response, duration) = deps.services().homo_request().request(&service_url).await?;
+) -> HomoServiceResponse {
+    let flights = deps.flights();
+    let (response, shared) = flights
+        .run(service_url.clone(), || async {
+            let checked_at = Utc::now();
+            let response = check_service(deps.clone(), &service_url).await;
+            // 保存は待たずに結果を返す。呼び出し元がキャンセルされても保存は続ける
+            spawn(save_check(
+                deps,
+                service_url.clone(),
+                response.clone(),
+                checked_at,
+            ));
+            response
+        })
+        .await;
+    if shared {
+        info!("Shared the in-flight check of {}", service_url);
+    }
+    response
+}
+
+/// Returns the cached result of the service if any, otherwise requests to it.
+/// `fresh` forces the request. The second element is when the result was cached.
+pub async fn request_service_cached(
+    deps: impl Container + 'static,
+    service_url: Url,
+    fresh: bool,
+) -> (HomoServiceResponse, Option<DateTime<Utc>>) {
+    if !fresh && deps.config().cache.ttl > Duration::default() {
+        match deps.repositories().check_result().get(&service_url).await {
+            Ok(Some(cached)) => return (cached.response, Some(cached.cached_at)),
+            Ok(None) => (),
+            Err(e) => {
+                warn!("Failed to access to check result cache: {}", e);
+            }
+        }
+    }
+
+    (request_service(deps, service_url).await, None)
+}
+
+/// Records the result of the service in the history and caches it.
+async fn save_check(
+    deps: impl Container + 'static,
+    service_url: Url,
+    response: HomoServiceResponse,
+    checked_at: DateTime<Utc>,
+) {
+    join!(
+        save_check_record(deps.clone(), &service_url, &response, checked_at),
+        save_check_result(deps, &service_url, &response)
+    );
+}
+
+/// Caches the result of the service.
+async fn save_check_result(
+    deps: impl Container + 'static,
+    service_url: &Url,
+    response: &HomoServiceResponse,
+) {
+    let ttl = deps.config().cache.ttl;
+    if ttl == Duration::default() {
+        return;
+    }
+
+    let result = CachedCheckResult {
+        response: response.clone(),
+        cached_at: Utc::now(),
+    };
+    if let Err(e) = deps
+        .repositories()
+        .check_result()
+        .save_cache(service_url, &result, ttl)
+        .await
+    {
+        warn!("Failed to access to check result cache: {}", e);
+    }
+}
+
+/// Records the result of the service in the history.
+async fn save_check_record(
+    deps: impl Container + 'static,
+    service_url: &Url,
+    response: &HomoServiceResponse,
+    checked_at: DateTime<Utc>,
+) {
+    let record = CheckRecord::from_response(service_url, response, checked_at);
+    if let Err(e) = deps.repositories().check_history().save(&record).await {
+        warn!("Failed to record check result: {}", e);
+    }
+}
+
+/// Aggregates the history of the services over each window in `StatsConfig`.
+pub async fn summarize_services(
+    deps: impl Container + 'static,
+    service_urls: &[Url],
+) -> Result<Vec<(StatsWindow, Vec<CheckSummary>)>, RepositoryError> {
+    let now = Utc::now();
+    let history = deps.repositories().check_history();
+    let windows = deps.config().stats.windows.clone();
+    let summaries = join_all(
+        windows
+            .iter()
+            .map(|window| history.summarize_by_urls(service_urls, window.since(now))),
+    )
+    .await;
+
+    windows
+        .into_iter()
+        .zip(summaries)
+        .map(|(window, summaries)| Ok((window, summaries?)))
+        .collect()
+}
+
+/// Checks the service, separately over IPv4 and IPv6 in dual-stack mode.
+async fn check_service(deps: impl Container + 'static, service_url: &Url) -> HomoServiceResponse {
+    if !deps.config().request.dual_stack {
+        return request_service_retrying(deps, service_url, None).await;
+    }
+
+    let (ipv4, ipv6) = join!(
+        request_service_retrying(deps.clone(), service_url, Some(IpVersion::V4)),
+        request_service_retrying(deps.clone(), service_url, Some(IpVersion::V6))
+    );
+
+    // 片方でも成功していればそちらを代表とする
+    let mut response = match ipv4.status {
+        HomoServiceStatus::Error | HomoServiceStatus::Blocked => ipv6.clone(),
+        _ => ipv4.clone(),
+    };
+    response.dual_stack = Some(Box::new(DualStackResponse { ipv4, ipv6 }));
+    response
+}
+
+/// Requests to the service with retries.
+async fn request_service_retrying(
+    deps: impl Container + 'static,
+    service_url: &Url,
+    version: Option<IpVersion>,
+) -> HomoServiceResponse {
+    let config = deps.config();
+    let retry = &config.retry;
+
+    let mut attempt = 1;
+    loop {
+        let mut response = request_service_once(deps.clone(), service_url, version).await;
+        response.attempts = attempt;
+
+        let retryable = match &response.error {
+            Some(error) => retry.is_retryable(error.kind),
+            None => false,
+        };
+        if !retryable || attempt >= retry.attempts {
+            return response;
+        }
+
+        let delay = retry.delay(attempt);
+        info!(
+            "Retrying {} in {:?} (attempt {}/{})",
+            service_url,
+            delay,
+            attempt + 1,
+            retry.attempts
+        );
+        delay_for(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Requests to the service once.
+async fn request_service_once(
+    deps: impl Container + 'static,
+    service_url: &Url,
+    version: Option<IpVersion>,
+) -> HomoServiceResponse {
+    let homo_request = deps.services().homo_request();
+    let result = match version {
+        Some(version) => homo_request.request_over(service_url, version).await,
+        None => homo_request.request(service_url).await,
+    };
+    let (response, duration) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            warn!("Failed to request to {}: {}", service_url, e);
+            return error_response(e);
+        }
+    };
 
     let remote_address = response.remote_address;
-    let status = validate_status(response).await;
+    // HttpClient が本文を読みながら判定していればそれを使い、本文を検証し直さない
+    let (status, evidence) = match response.judgment.clone() {
+        Some(judgment) => judgment,
+        None => deps.config().validators.judge(&response).await,
+    };
 
-    Ok(HomoServiceResponse {
+    // 別ドメインを経由して対象にリダイレクトしたもの (www. の有無などサブドメインの違いは含めない)
+    let status = match status {
+        HomoServiceStatus::RedirectResponse
+        | HomoServiceStatus::RedirectContent
+        | HomoServiceStatus::RedirectScript
+            if !response.redirects.is_empty() && !is_same_site(&response.url, service_url) =>
+        {
+            HomoServiceStatus::Indirect
+        }
+        otherwise => otherwise,
+    };
+
+    // 4xx/5xx でもリダイレクトできていれば OK とする (GitHub Pages の 404.html など)
+    let error = match status {
+        HomoServiceStatus::Invalid => HomoServiceError::from_status(response.status),
+        _ => None,
+    };
+    let status = if error.is_some() {
+        HomoServiceStatus::Error
+    } else {
+        status
+    };
+
+    HomoServiceResponse {
         status,
         remote_address,
         duration,
-    })
+        evidence,
+        redirects: response.redirects,
+        error,
+        attempts: 1,
+        timing: response.timing,
+        dual_stack: None,
+    }
 }
 
-/// Validates the response from the service.
-async fn validate_status(response: HttpResponse) -> HomoServiceStatus {
-    match response.validate::<ResponseHeaderValidator>().await {
-        Some(s) => s,
-        None => response
-            .validate::<ResponseHtmlValidator>()
-            .await
-            .unwrap_or(HomoServiceStatus::Invalid),
+/// Makes `HomoServiceResponse` from the error of `HomoRequestService`.
+fn error_response(error: ServiceError) -> HomoServiceResponse {
+    let error = match error.into_cause().downcast::<RedirectError>() {
+        Ok(redirect_error) => return redirect_error_response(&redirect_error),
+        Err(e) => e,
+    };
+    let error = match error.downcast::<HomoServiceError>() {
+        Ok(service_error) => *service_error,
+        Err(e) => HomoServiceError::new(HomoServiceErrorKind::Unknown, e.to_string()),
+    };
+
+    let status = match error.kind {
+        HomoServiceErrorKind::Blocked => HomoServiceStatus::Blocked,
+        _ => HomoServiceStatus::Error,
+    };
+    HomoServiceResponse {
+        status,
+        remote_address: None,
+        duration: Duration::default(),
+        evidence: None,
+        redirects: vec![],
+        error: Some(error),
+        attempts: 1,
+        timing: None,
+        dual_stack: None,
+    }
+}
+
+/// Makes `HomoServiceResponse` for redirect loops and excessive redirects.
+fn redirect_error_response(error: &RedirectError) -> HomoServiceResponse {
+    let status = match error {
+        RedirectError::Loop(_) => HomoServiceStatus::RedirectLoop,
+        RedirectError::TooMany(_) => HomoServiceStatus::TooManyRedirects,
+    };
+    let redirects = error.redirects().to_vec();
+
+    HomoServiceResponse {
+        status,
+        remote_address: None,
+        duration: redirects.iter().map(|hop| hop.duration).sum(),
+        evidence: None,
+        redirects,
+        error: None,
+        attempts: 1,
+        timing: None,
+        dual_stack: None,
     }
 }
 
@@ -75,12 +353,16 @@ pub async fn fetch_avatar(deps: impl Container + 'static, provider: Arc<Provider
         Provider::Twitter(sn) => {
             let response = match avatar_srv.fetch_twitter(sn).await {
                 Ok(res) => res,
+                Err(ServiceError::NotFound(_)) => {
+                    info!("Twitter user not found: {}", sn);
+                    return None;
+                }
                 Err(e) => {
                     warn!("Failed to fetch twitter intent: {}", e);
                     return None;
                 }
             };
-            let html = response.body;
+            let html = response.text().unwrap_or_default();
             if let Some(capture) = REGEX_USER_AVATAR.captures(&html) {
                 Url::parse(&capture[1]).unwrap_or_warn("Invalid URL")
             } else {
@@ -94,15 +376,19 @@ pub async fn fetch_avatar(deps: impl Container + 'static, provider: Arc<Provider
         } => {
             let response = match avatar_srv.fetch_mastodon(screen_name, domain).await {
                 Ok(res) => res,
+                Err(ServiceError::NotFound(_)) => {
+                    info!("Mastodon user not found: {}@{}", screen_name, domain);
+                    return None;
+                }
                 Err(e) => {
-                    warn!("Failed to fetch twitter intent: {}", e);
+                    warn!("Failed to fetch Mastodon user: {}", e);
                     return None;
                 }
             };
-            let user = serde_json::from_str::<JsonValue>(&response.body)
+            let user = serde_json::from_slice::<JsonValue>(&response.body)
                 .unwrap_or_warn("Invalid Mastodon user JSON")?;
             if let JsonValue::String(s) = &user["icon"]["url"] {
-                Url::parse(&s).unwrap_or_warn("Invalid URL")
+                Url::parse(s).unwrap_or_warn("Invalid URL")
             } else {
                 let message = format!("user.icon.url was not string: {}", &user["icon"]["url"]);
                 warn!("{}", message);
@@ -113,7 +399,7 @@ pub async fn fetch_avatar(deps: impl Container + 'static, provider: Arc<Provider
 
     // キャッシュ
     match avatar_repo
-        .save_cache(&provider, &fetched.to_string(), Duration::from_secs(86400))
+        .save_cache(&provider, fetched.as_str(), Duration::from_secs(86400))
         .await
     {
         Ok(()) => {
@@ -136,7 +422,7 @@ pub fn attach_avatar_resolver(
     let mut txmap: HashMap<Provider, Sender<Option<Url>>> = HashMap::new();
     for service in services {
         let sn = &service.provider;
-        if let Some(tx) = txmap.get(&sn) {
+        if let Some(tx) = txmap.get(sn) {
             attached.push((service, tx.subscribe()));
         } else {
             let (tx, rx) = channel(4);
@@ -4,31 +4,40 @@ mod repository;
 mod service;
 
 use self::{
-    repository::{AvatarRepository, UserRepository},
+    repository::{AvatarRepository, CheckHistoryRepository, CheckResultRepository, UserRepository},
     service::{AvatarService, HomoRequestService},
 };
 use homochecker_rs::{
-    repository::Repositories as RepositoriesInterface, service::Services as ServicesInterface,
+    action::CheckFlights,
+    client::HttpClient,
+    config::{Config, RequestConfig},
+    repository::Repositories as RepositoriesInterface,
+    service::{RequestLimiter, Services as ServicesInterface},
+    validation::TargetUrls,
     Container as ContainerInterface,
 };
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
 use redis::aio::Connection as RedisConnection;
-use reqwest::{redirect::Policy as RedirectPolicy, Client as ReqwestClient};
-use tokio::sync::{Mutex, Semaphore};
+use reqwest::Client as ReqwestClient;
+use tokio::sync::Mutex;
 use tokio_postgres::Client as PostgresClient;
 
 #[derive(Clone)]
 pub struct Container {
     repositories: Repositories,
     services: Services,
+    config: Arc<Config>,
+    flights: Arc<CheckFlights>,
 }
 
 impl Container {
-    pub fn new(repositories: Repositories, services: Services) -> Container {
+    pub fn new(repositories: Repositories, services: Services, config: Config) -> Container {
         Container {
             repositories,
             services,
+            config: Arc::new(config),
+            flights: Arc::new(CheckFlights::new()),
         }
     }
 }
@@ -44,19 +53,38 @@ impl ContainerInterface for Container {
     fn services(&self) -> Services {
         self.services.clone()
     }
+
+    fn config(&self) -> Arc<Config> {
+        self.config.clone()
+    }
+
+    fn flights(&self) -> Arc<CheckFlights> {
+        self.flights.clone()
+    }
 }
 
 #[derive(Clone)]
 pub struct Repositories {
     postgres: Arc<PostgresClient>,
     redis: Arc<Mutex<RedisConnection>>,
+    check_result: CheckResultRepository,
 }
 
 impl Repositories {
     pub fn new(postgres: PostgresClient, redis: RedisConnection) -> Repositories {
+        let redis = Arc::new(Mutex::new(redis));
         Repositories {
             postgres: Arc::new(postgres),
-            redis: Arc::new(Mutex::new(redis)),
+            check_result: CheckResultRepository::redis(redis.clone()),
+            redis,
+        }
+    }
+
+    /// Caches check results in this process instead of Redis.
+    pub fn with_memory_check_cache(self) -> Repositories {
+        Repositories {
+            check_result: CheckResultRepository::memory(),
+            ..self
         }
     }
 }
@@ -64,6 +92,8 @@ impl Repositories {
 impl RepositoriesInterface for Repositories {
     type User = UserRepository;
     type Avatar = AvatarRepository;
+    type CheckResult = CheckResultRepository;
+    type CheckHistory = CheckHistoryRepository;
 
     fn user(&self) -> UserRepository {
         UserRepository::new(self.postgres.clone())
@@ -72,37 +102,55 @@ impl RepositoriesInterface for Repositories {
     fn avatar(&self) -> AvatarRepository {
         AvatarRepository::new(self.redis.clone())
     }
+
+    fn check_result(&self) -> CheckResultRepository {
+        self.check_result.clone()
+    }
+
+    fn check_history(&self) -> CheckHistoryRepository {
+        CheckHistoryRepository::new(self.postgres.clone())
+    }
 }
 
 #[derive(Clone)]
 pub struct Services {
     avatar_client: Arc<ReqwestClient>,
-    homo_client: Arc<ReqwestClient>,
+    mastodon_client: Arc<HttpClient>,
+    homo_client: Arc<HttpClient>,
+    request_config: Arc<RequestConfig>,
+    target_urls: Arc<TargetUrls>,
 }
 
 impl Services {
-    pub fn new() -> Services {
+    pub fn new(config: &Config) -> Services {
+        let request_config = config.request.clone();
         let avatar_client = Arc::new(ReqwestClient::new());
+        // Mastodon のドメインはユーザーが決めるので、宛先を制限したクライアントで取得する
+        let mastodon_client = Arc::new(
+            HttpClient::new(request_config.timeout)
+                .unwrap()
+                .with_egress(request_config.egress.clone()),
+        );
+        let limiter = Arc::new(RequestLimiter::new(
+            request_config.max_concurrency,
+            request_config.max_concurrency_per_address,
+        ));
+        // リダイレクトは各ホップを記録するために HomoRequestService で追う
         let homo_client = Arc::new(
-            ReqwestClient::builder()
-                .redirect(RedirectPolicy::custom(|attempt| {
-                    // HTTP -> HTTPS のリダイレクトだけ追う
-                    let prev = &attempt.previous()[0];
-                    let next = attempt.url();
-                    if prev.domain() == next.domain() {
-                        attempt.follow()
-                    } else {
-                        attempt.stop()
-                    }
-                }))
-                .timeout(Duration::from_secs(5))
-                .build()
-                .unwrap(),
+            HttpClient::new(request_config.timeout)
+                .unwrap()
+                .with_egress(request_config.egress.clone())
+                .with_max_body_size(request_config.max_body_size)
+                .with_validators(config.validators.clone())
+                .with_limiter(limiter),
         );
 
         Services {
             avatar_client,
+            mastodon_client,
             homo_client,
+            request_config: Arc::new(request_config),
+            target_urls: config.target_urls.clone(),
         }
     }
 }
@@ -112,10 +160,14 @@ impl ServicesInterface for Services {
     type HomoRequest = HomoRequestService;
 
     fn avatar(&self) -> AvatarService {
-        AvatarService::new(self.avatar_client.clone())
+        AvatarService::new(self.avatar_client.clone(), self.mastodon_client.clone())
     }
 
     fn homo_request(&self) -> HomoRequestService {
-        HomoRequestService::new(self.homo_client.clone())
+        HomoRequestService::new(
+            self.homo_client.clone(),
+            self.request_config.clone(),
+            self.target_urls.clone(),
+        )
     }
 }
@@ -3,14 +3,22 @@
 use homochecker_rs::{
     domain::Provider,
     repository::{
-        AvatarRepository as AvatarRepositoryInterface, RepositoryError, User,
-        UserRepository as UserRepositoryInterface,
+        AvatarRepository as AvatarRepositoryInterface, CachedCheckResult,
+        CheckHistoryRepository as CheckHistoryRepositoryInterface, CheckRecord,
+        CheckResultRepository as CheckResultRepositoryInterface, CheckSummary, RepositoryError,
+        User, UserRepository as UserRepositoryInterface,
     },
 };
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
-use redis::{aio::Connection, AsyncCommands};
+use chrono::{DateTime, Utc};
+use redis::{aio::Connection, AsyncCommands, ErrorKind as RedisErrorKind, RedisError};
 use tokio::sync::Mutex;
 use tokio_postgres::{Client, Error as PostgresError, Row};
 use url::Url;
@@ -20,11 +28,11 @@ pub trait FromPostgresRow
 where
     Self: Sized,
 {
-    fn from_row(row: &Row) -> Result<Self, PostgresError>;
+    fn from_row(row: &Row) -> Result<Self, Box<dyn Error + Send + Sync>>;
 }
 
 impl FromPostgresRow for User {
-    fn from_row(row: &Row) -> Result<Self, PostgresError> {
+    fn from_row(row: &Row) -> Result<Self, Box<dyn Error + Send + Sync>> {
         Ok(User {
             id: row.try_get("id")?,
             screen_name: row.try_get("screen_name")?,
@@ -34,6 +42,22 @@ impl FromPostgresRow for User {
     }
 }
 
+impl FromPostgresRow for CheckRecord {
+    fn from_row(row: &Row) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let service_url: String = row.try_get("url")?;
+        let duration: f64 = row.try_get("duration")?;
+        let error: Option<String> = row.try_get("error")?;
+        Ok(CheckRecord {
+            service_url: Url::parse(&service_url)?,
+            checked_at: row.try_get("checked_at")?,
+            status: row.try_get("status")?,
+            ip: row.try_get("ip")?,
+            duration: Duration::from_secs_f64(duration.max(0.0)),
+            error: error.map(|kind| kind.parse()).transpose()?,
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct UserRepository(Arc<Client>);
 
@@ -49,18 +73,24 @@ impl UserRepositoryInterface for UserRepository {
         let client = &self.0;
         let row = client
             .query_one(r#"SELECT COUNT(*)::INTEGER AS records FROM "users";"#, &[])
-            .await?;
-        Ok(row.try_get::<_, i32>("records")? as usize)
+            .await
+            .map_err(postgres_error)?;
+        let records: i32 = row
+            .try_get("records")
+            .map_err(|e| RepositoryError::InvalidData(e.into()))?;
+        Ok(records as usize)
     }
 
     async fn fetch_all(&self) -> Result<Vec<User>, RepositoryError> {
         let client = &self.0;
         let rows = client
             .query(r#"SELECT * FROM "users" ORDER BY "id";"#, &[])
-            .await?;
+            .await
+            .map_err(postgres_error)?;
 
         rows.iter().try_fold(vec![], |mut users, row| {
-            users.push(User::from_row(row)?);
+            let user = User::from_row(row).map_err(RepositoryError::InvalidData)?;
+            users.push(user);
             Ok(users)
         })
     }
@@ -72,15 +102,130 @@ impl UserRepositoryInterface for UserRepository {
                 r#"SELECT * FROM "users" WHERE "screen_name" = $1 ORDER BY "id";"#,
                 &[&screen_name],
             )
-            .await?;
+            .await
+            .map_err(postgres_error)?;
 
         rows.iter().try_fold(vec![], |mut users, row| {
-            users.push(User::from_row(row)?);
+            let user = User::from_row(row).map_err(RepositoryError::InvalidData)?;
+            users.push(user);
             Ok(users)
         })
     }
 }
 
+#[derive(Clone)]
+pub struct CheckHistoryRepository(Arc<Client>);
+
+impl CheckHistoryRepository {
+    pub fn new(client: Arc<Client>) -> CheckHistoryRepository {
+        CheckHistoryRepository(client)
+    }
+}
+
+#[async_trait]
+impl CheckHistoryRepositoryInterface for CheckHistoryRepository {
+    async fn save(&self, record: &CheckRecord) -> Result<(), RepositoryError> {
+        let client = &self.0;
+        client
+            .execute(
+                r#"INSERT INTO "check_results" ("url", "checked_at", "status", "ip", "duration", "error") VALUES ($1, $2, $3, $4, $5, $6);"#,
+                &[
+                    &record.service_url.as_str(),
+                    &record.checked_at,
+                    &record.status,
+                    &record.ip,
+                    &record.duration.as_secs_f64(),
+                    &record.error.map(|kind| kind.as_str()),
+                ],
+            )
+            .await
+            .map_err(postgres_error)?;
+
+        Ok(())
+    }
+
+    async fn count_by_urls(&self, service_urls: &[Url]) -> Result<usize, RepositoryError> {
+        let client = &self.0;
+        let urls: Vec<_> = service_urls.iter().map(Url::as_str).collect();
+        let row = client
+            .query_one(
+                r#"SELECT COUNT(*)::INTEGER AS records FROM "check_results" WHERE "url" = ANY($1);"#,
+                &[&urls],
+            )
+            .await
+            .map_err(postgres_error)?;
+        let records: i32 = row
+            .try_get("records")
+            .map_err(|e| RepositoryError::InvalidData(e.into()))?;
+        Ok(records as usize)
+    }
+
+    async fn fetch_by_urls(
+        &self,
+        service_urls: &[Url],
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<CheckRecord>, RepositoryError> {
+        let client = &self.0;
+        let urls: Vec<_> = service_urls.iter().map(Url::as_str).collect();
+        let rows = client
+            .query(
+                r#"SELECT * FROM "check_results" WHERE "url" = ANY($1) ORDER BY "checked_at" DESC, "id" DESC LIMIT $2 OFFSET $3;"#,
+                &[&urls, &(limit as i64), &(offset as i64)],
+            )
+            .await
+            .map_err(postgres_error)?;
+
+        rows.iter().try_fold(vec![], |mut records, row| {
+            let record = CheckRecord::from_row(row).map_err(RepositoryError::InvalidData)?;
+            records.push(record);
+            Ok(records)
+        })
+    }
+
+    async fn summarize_by_urls(
+        &self,
+        service_urls: &[Url],
+        since: DateTime<Utc>,
+    ) -> Result<Vec<CheckSummary>, RepositoryError> {
+        let client = &self.0;
+        let urls: Vec<_> = service_urls.iter().map(Url::as_str).collect();
+        // 状態の分類とパーセンタイルは CheckSummary::summarize で計算する
+        let rows = client
+            .query(
+                r#"SELECT "url", "status", "duration" FROM "check_results" WHERE "url" = ANY($1) AND "checked_at" >= $2;"#,
+                &[&urls, &since],
+            )
+            .await
+            .map_err(postgres_error)?;
+
+        let mut checks: BTreeMap<String, Vec<(String, Duration)>> = BTreeMap::new();
+        for row in &rows {
+            let (url, status, duration): (String, String, f64) = (
+                row.try_get("url")
+                    .map_err(|e| RepositoryError::InvalidData(e.into()))?,
+                row.try_get("status")
+                    .map_err(|e| RepositoryError::InvalidData(e.into()))?,
+                row.try_get("duration")
+                    .map_err(|e| RepositoryError::InvalidData(e.into()))?,
+            );
+            checks
+                .entry(url)
+                .or_default()
+                .push((status, Duration::from_secs_f64(duration.max(0.0))));
+        }
+
+        checks
+            .into_iter()
+            .try_fold(vec![], |mut summaries, (url, checks)| {
+                let service_url =
+                    Url::parse(&url).map_err(|e| RepositoryError::InvalidData(e.into()))?;
+                summaries.push(CheckSummary::summarize(service_url, checks));
+                Ok(summaries)
+            })
+    }
+}
+
 #[derive(Clone)]
 pub struct AvatarRepository(Arc<Mutex<Connection>>);
 
@@ -95,9 +240,12 @@ impl AvatarRepositoryInterface for AvatarRepository {
     async fn get(&self, provider: &Provider) -> Result<Option<Url>, RepositoryError> {
         let key = provider.to_cache_key();
         let mut locked = self.0.lock().await;
-        let cached: Option<String> = locked.get(&key).await?;
+        let cached: Option<String> = locked.get(&key).await.map_err(redis_error)?;
         match cached {
-            Some(url) => Ok(Some(Url::parse(&url)?)),
+            Some(url) => {
+                let url = Url::parse(&url).map_err(|e| RepositoryError::InvalidData(e.into()))?;
+                Ok(Some(url))
+            }
             None => Ok(None),
         }
     }
@@ -115,9 +263,115 @@ impl AvatarRepositoryInterface for AvatarRepository {
             .arg(url)
             .arg("EX")
             .arg(age.as_secs())
-            .query_async(&mut *locked)
-            .await?;
+            .query_async::<_, ()>(&mut *locked)
+            .await
+            .map_err(redis_error)?;
 
         Ok(())
     }
 }
+
+/// Caches check results in Redis or in this process.
+#[derive(Clone)]
+pub enum CheckResultRepository {
+    Redis(Arc<Mutex<Connection>>),
+    Memory(Arc<Mutex<HashMap<Url, (CachedCheckResult, Instant)>>>),
+}
+
+impl CheckResultRepository {
+    pub fn redis(conn: Arc<Mutex<Connection>>) -> CheckResultRepository {
+        CheckResultRepository::Redis(conn)
+    }
+
+    pub fn memory() -> CheckResultRepository {
+        CheckResultRepository::Memory(Arc::new(Mutex::new(HashMap::new())))
+    }
+}
+
+#[async_trait]
+impl CheckResultRepositoryInterface for CheckResultRepository {
+    async fn get(&self, service_url: &Url) -> Result<Option<CachedCheckResult>, RepositoryError> {
+        match self {
+            CheckResultRepository::Redis(conn) => {
+                let key = format!("check:{}", service_url);
+                let mut locked = conn.lock().await;
+                let cached: Option<String> = locked.get(&key).await.map_err(redis_error)?;
+                match cached {
+                    Some(json) => {
+                        let result = serde_json::from_str(&json)
+                            .map_err(|e| RepositoryError::InvalidData(e.into()))?;
+                        Ok(Some(result))
+                    }
+                    None => Ok(None),
+                }
+            }
+            CheckResultRepository::Memory(cache) => {
+                let mut locked = cache.lock().await;
+                match locked.get(service_url) {
+                    Some((result, expires)) if *expires > Instant::now() => {
+                        Ok(Some(result.clone()))
+                    }
+                    Some(_) => {
+                        locked.remove(service_url);
+                        Ok(None)
+                    }
+                    None => Ok(None),
+                }
+            }
+        }
+    }
+
+    async fn save_cache(
+        &self,
+        service_url: &Url,
+        result: &CachedCheckResult,
+        age: Duration,
+    ) -> Result<(), RepositoryError> {
+        match self {
+            CheckResultRepository::Redis(conn) => {
+                let key = format!("check:{}", service_url);
+                let json = serde_json::to_string(result)
+                    .map_err(|e| RepositoryError::InvalidData(e.into()))?;
+                let mut locked = conn.lock().await;
+                redis::cmd("SET")
+                    .arg(&key)
+                    .arg(json)
+                    .arg("EX")
+                    .arg(age.as_secs().max(1))
+                    .query_async::<_, ()>(&mut *locked)
+                    .await
+                    .map_err(redis_error)?;
+            }
+            CheckResultRepository::Memory(cache) => {
+                let now = Instant::now();
+                let mut locked = cache.lock().await;
+                // 期限切れのものはここで掃除する
+                locked.retain(|_, (_, expires)| *expires > now);
+                locked.insert(service_url.clone(), (result.clone(), now + age));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts PostgreSQL error.
+/// Errors with SQLSTATE are returned by the server, and others are connection failures.
+fn postgres_error(error: PostgresError) -> RepositoryError {
+    if error.code().is_some() {
+        RepositoryError::Upstream(error.into())
+    } else {
+        RepositoryError::Unavailable(error.into())
+    }
+}
+
+/// Converts Redis error.
+fn redis_error(error: RedisError) -> RepositoryError {
+    if error.is_io_error() || error.is_connection_dropped() || error.is_connection_refusal() {
+        RepositoryError::Unavailable(error.into())
+    } else if error.kind() == RedisErrorKind::TypeError {
+        RepositoryError::InvalidData(error.into())
+    } else {
+        RepositoryError::Upstream(error.into())
+    }
+}
@@ -1,28 +1,38 @@
 //! Contais adapters for `UserRepository`.
 
 use homochecker_rs::{
-    domain::HttpResponse,
+    client::HttpClient,
+    config::RequestConfig,
+    domain::{
+        HomoServiceError, HomoServiceErrorKind, HttpResponse, IpVersion, RedirectError, RedirectHop,
+    },
     service::{
         AvatarService as AvatarServiceInterface, HomoRequestService as HomoRequestServiceInterface,
         ServiceError,
     },
+    validation::{is_downgrade, TargetUrls},
 };
 use std::{
-    collections::HashMap,
+    error::Error,
+    io::{Error as IoError, ErrorKind as IoErrorKind},
     sync::Arc,
     time::{Duration, Instant},
 };
 
 use async_trait::async_trait;
-use reqwest::Client;
+use hyper::Error as HyperError;
+use native_tls::Error as NativeTlsError;
+use reqwest::{Client, Error as ReqwestError, Response, StatusCode};
 use url::Url;
 
+/// Fetches avatars. The Twitter client follows redirects, while Mastodon instances are chosen by
+/// users and accessed only through the `HttpClient` which enforces the egress policy.
 #[derive(Clone)]
-pub struct AvatarService(Arc<Client>);
+pub struct AvatarService(Arc<Client>, Arc<HttpClient>);
 
 impl AvatarService {
-    pub fn new(client: Arc<Client>) -> AvatarService {
-        AvatarService(client)
+    pub fn new(twitter_client: Arc<Client>, mastodon_client: Arc<HttpClient>) -> AvatarService {
+        AvatarService(twitter_client, mastodon_client)
     }
 }
 
@@ -35,19 +45,8 @@ impl AvatarServiceInterface for AvatarService {
             screen_name
         ));
 
-        let response = request.send().await?;
-        let status = response.status();
-        let remote_address = response.remote_addr();
-        let mut headers = HashMap::new();
-        for (k, v) in response.headers() {
-            headers.insert(k.as_str().to_owned(), v.to_str()?.to_owned());
-        }
-        Ok(HttpResponse {
-            status,
-            remote_address,
-            headers,
-            body: response.text().await?,
-        })
+        let response = request.send().await.map_err(classify_request_error)?;
+        into_http_response(response).await
     }
 
     async fn fetch_mastodon(
@@ -55,58 +54,192 @@ impl AvatarServiceInterface for AvatarService {
         screen_name: &str,
         domain: &str,
     ) -> Result<HttpResponse, ServiceError> {
-        let client = &self.0;
-        let request = client
-            .get(&format!("https://{}/users/{}.json", domain, screen_name))
-            .header("Accept", "application/json");
-
-        let response = request.send().await?;
-        let status = response.status();
-        let remote_address = response.remote_addr();
-        let mut headers = HashMap::new();
-        for (k, v) in response.headers() {
-            headers.insert(k.as_str().to_owned(), v.to_str()?.to_owned());
+        let client = &self.1;
+        let url = Url::parse(&format!("https://{}/users/{}.json", domain, screen_name))
+            .map_err(|e| ServiceError::InvalidData(e.into()))?;
+
+        let response = client.fetch(&url, None).await?;
+        if response.status == StatusCode::NOT_FOUND {
+            return Err(ServiceError::NotFound(url.to_string()));
         }
-        Ok(HttpResponse {
-            status,
-            remote_address,
-            headers,
-            body: response.text().await?,
-        })
+        Ok(response)
     }
 }
 
+/// Converts a reqwest response, reporting 404 as `ServiceError::NotFound`.
+async fn into_http_response(response: Response) -> Result<HttpResponse, ServiceError> {
+    let status = response.status();
+    let url = response.url().clone();
+    if status == StatusCode::NOT_FOUND {
+        return Err(ServiceError::NotFound(url.to_string()));
+    }
+
+    let remote_address = response.remote_addr();
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(k, v)| {
+            let value = String::from_utf8_lossy(v.as_bytes()).into_owned();
+            (k.as_str().to_owned(), value)
+        })
+        .collect();
+    Ok(HttpResponse {
+        url,
+        status,
+        remote_address,
+        headers,
+        body: response
+            .bytes()
+            .await
+            .map_err(classify_body_error)?
+            .to_vec(),
+        redirects: vec![],
+        timing: None,
+        judgment: None,
+    })
+}
+
 #[derive(Clone)]
-pub struct HomoRequestService(Arc<Client>);
+pub struct HomoRequestService(Arc<HttpClient>, Arc<RequestConfig>, Arc<TargetUrls>);
 
 impl HomoRequestService {
-    pub fn new(client: Arc<Client>) -> HomoRequestService {
-        HomoRequestService(client)
+    pub fn new(
+        client: Arc<HttpClient>,
+        config: Arc<RequestConfig>,
+        targets: Arc<TargetUrls>,
+    ) -> HomoRequestService {
+        HomoRequestService(client, config, targets)
+    }
+
+    /// Requests following redirects.
+    async fn request_following(
+        &self,
+        service_url: &Url,
+        version: Option<IpVersion>,
+    ) -> Result<(HttpResponse, Duration), ServiceError> {
+        let client = &self.0;
+        let max_redirects = self.1.max_redirects;
+        let max_cross_domain_redirects = self.1.max_cross_domain_redirects;
+        let targets = &self.2;
+
+        let start = Instant::now();
+        let mut queue = Duration::default();
+
+        let mut url = service_url.clone();
+        let mut redirects = vec![];
+        let mut cross_domain_redirects = 0;
+        let mut response = loop {
+            let hop_start = Instant::now();
+            let response = client.fetch(&url, version).await?;
+            // 同時接続数の制限による待ち時間は応答時間に含めない
+            let hop_queue = response.timing.map(|t| t.queue).unwrap_or_default();
+            queue += hop_queue;
+            let status = response.status;
+            let location = response.headers.get("location").cloned();
+
+            // 同一ホスト内 (HTTP -> HTTPS など) のリダイレクトは常に追う
+            // 別ドメインへのリダイレクトと HTTPS -> HTTP への格下げは上限まで追うが、対象 URL には飛ばない
+            let next = match &location {
+                Some(loc) if status.is_redirection() => url.join(loc).ok(),
+                _ => None,
+            };
+            let next = match next {
+                Some(next) if next.host_str() == url.host_str() && !is_downgrade(&url, &next) => {
+                    next
+                }
+                Some(next)
+                    if cross_domain_redirects < max_cross_domain_redirects
+                        && (next.scheme() == "http" || next.scheme() == "https")
+                        && !targets.matches(&next) =>
+                {
+                    cross_domain_redirects += 1;
+                    next
+                }
+                _ => break response,
+            };
+
+            redirects.push(RedirectHop {
+                url: url.clone(),
+                status,
+                location,
+                duration: hop_start.elapsed() - hop_queue,
+            });
+            if next == url || redirects.iter().any(|hop| hop.url == next) {
+                return Err(RedirectError::Loop(redirects).into());
+            }
+            if redirects.len() >= max_redirects {
+                return Err(RedirectError::TooMany(redirects).into());
+            }
+            url = next;
+        };
+
+        response.redirects = redirects;
+        Ok((response, start.elapsed() - queue))
     }
 }
 
 #[async_trait]
 impl HomoRequestServiceInterface for HomoRequestService {
     async fn request(&self, service_url: &Url) -> Result<(HttpResponse, Duration), ServiceError> {
-        let client = &self.0;
-        let start = Instant::now();
-        let response = client.get(&service_url[..]).send().await?;
-        let duration = start.elapsed();
-
-        let status = response.status();
-        let remote_address = response.remote_addr();
-        let mut headers = HashMap::new();
-        for (k, v) in response.headers() {
-            headers.insert(k.as_str().to_owned(), v.to_str()?.to_owned());
+        self.request_following(service_url, None).await
+    }
+
+    async fn request_over(
+        &self,
+        service_url: &Url,
+        version: IpVersion,
+    ) -> Result<(HttpResponse, Duration), ServiceError> {
+        self.request_following(service_url, Some(version)).await
+    }
+}
+
+/// Classifies the error in sending a request.
+fn classify_request_error(error: ReqwestError) -> HomoServiceError {
+    let message = error.to_string();
+    if error.is_timeout() {
+        return HomoServiceError::new(HomoServiceErrorKind::Timeout, message);
+    }
+
+    // 原因を辿って型で判定する
+    // 最上位の reqwest のエラーは URL を含むので文字列では判定しない
+    let mut kind = HomoServiceErrorKind::Unknown;
+    let mut source = error.source();
+    while let Some(cause) = source {
+        if let Some(io_error) = cause.downcast_ref::<IoError>() {
+            match io_error.kind() {
+                IoErrorKind::ConnectionRefused => {
+                    return HomoServiceError::new(HomoServiceErrorKind::ConnectionRefused, message)
+                }
+                IoErrorKind::TimedOut => {
+                    return HomoServiceError::new(HomoServiceErrorKind::Timeout, message)
+                }
+                _ => (),
+            }
         }
-        Ok((
-            HttpResponse {
-                status,
-                remote_address,
-                headers,
-                body: response.text().await?,
-            },
-            duration,
-        ))
+        if cause.downcast_ref::<NativeTlsError>().is_some() {
+            return HomoServiceError::new(HomoServiceErrorKind::Tls, message);
+        }
+        if let Some(hyper_error) = cause.downcast_ref::<HyperError>() {
+            if hyper_error.is_connect() {
+                kind = HomoServiceErrorKind::Connection;
+                // 名前解決の失敗は hyper 内部の非公開の型なので表示で判定する
+                if hyper_error.to_string().contains("dns error") {
+                    return HomoServiceError::new(HomoServiceErrorKind::Dns, message);
+                }
+            }
+        }
+        source = cause.source();
     }
+
+    HomoServiceError::new(kind, message)
+}
+
+/// Classifies the error in reading a response body.
+fn classify_body_error(error: ReqwestError) -> HomoServiceError {
+    let kind = if error.is_timeout() {
+        HomoServiceErrorKind::Timeout
+    } else {
+        HomoServiceErrorKind::BodyDecode
+    };
+    HomoServiceError::new(kind, error.to_string())
 }
@@ -2,18 +2,33 @@
 
 use super::data::{
     CheckEventInitializeData, CheckEventResponseData, CheckQueryParameter, CheckResponseFormat,
-    ListJsonResponse, ListQueryParameter, ListResponseFormat,
+    ErrorResponse, HistoryJsonResponse, HistoryJsonResponseResult, HistoryQueryParameter,
+    ListJsonResponse, ListQueryParameter, ListResponseFormat, StatsJsonResponse,
+    StatsJsonResponseWindow,
 };
 use crate::{
-    action::{attach_avatar_resolver, fetch_avatar, request_service},
+    action::{attach_avatar_resolver, fetch_avatar, request_service_cached, summarize_services},
     domain::{HomoService, Provider},
-    repository::{Repositories, User, UserRepository},
+    repository::{CheckHistoryRepository, Repositories, RepositoryError, User, UserRepository},
+    service::ServiceError,
     Container,
 };
-use std::{convert::Infallible, iter::repeat, str::FromStr, sync::Arc};
+use std::{
+    convert::Infallible,
+    fmt::Display,
+    future::Future,
+    iter::repeat,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
 
-use futures::future::join_all;
-use log::{error, warn};
+use futures::{
+    future::{abortable, join_all, AbortHandle},
+    Stream,
+};
+use log::{error, info, warn};
 use serde_json::Value as JsonValue;
 use tokio::{join, spawn, sync::mpsc::channel as tokio_channel};
 use url::Url;
@@ -23,6 +38,132 @@ use warp::{
     redirect, reply, sse, Reply,
 };
 
+/// It can be reported as an error response.
+trait ToErrorResponse: Display {
+    /// Returns the HTTP status code.
+    fn status_code(&self) -> StatusCode;
+
+    /// Returns the machine-readable error code.
+    fn error_code(&self) -> &'static str;
+}
+
+impl ToErrorResponse for RepositoryError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            RepositoryError::NotFound(_) => StatusCode::NOT_FOUND,
+            RepositoryError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            RepositoryError::InvalidData(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            RepositoryError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_code(&self) -> &'static str {
+        match self {
+            RepositoryError::NotFound(_) => "NOT_FOUND",
+            RepositoryError::Unavailable(_) => "UNAVAILABLE",
+            RepositoryError::InvalidData(_) => "INVALID_DATA",
+            RepositoryError::Upstream(_) => "UPSTREAM_ERROR",
+        }
+    }
+}
+
+impl ToErrorResponse for ServiceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServiceError::NotFound(_) => StatusCode::NOT_FOUND,
+            ServiceError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ServiceError::InvalidData(_) | ServiceError::Upstream(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+
+    fn error_code(&self) -> &'static str {
+        match self {
+            ServiceError::NotFound(_) => "NOT_FOUND",
+            ServiceError::Unavailable(_) => "UNAVAILABLE",
+            ServiceError::InvalidData(_) => "INVALID_DATA",
+            ServiceError::Upstream(_) => "UPSTREAM_ERROR",
+        }
+    }
+}
+
+/// Aborts the spawned tasks when dropped.
+#[derive(Default)]
+struct TaskGuard(Vec<AbortHandle>);
+
+impl TaskGuard {
+    /// Spawns the task which is aborted with this guard.
+    fn spawn(&mut self, task: impl Future<Output = ()> + Send + 'static) {
+        let (task, handle) = abortable(task);
+        spawn(task);
+        self.0.push(handle);
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        for handle in &self.0 {
+            handle.abort();
+        }
+    }
+}
+
+/// Wraps the stream to abort the tasks when it is dropped.
+struct GuardedStream<S> {
+    stream: S,
+    _guard: TaskGuard,
+}
+
+impl<S: Stream + Unpin> Stream for GuardedStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<S::Item>> {
+        Pin::new(&mut self.stream).poll_next(cx)
+    }
+}
+
+/// Makes an error response.
+fn error_reply(status: StatusCode, code: &str, message: impl Into<String>) -> Box<dyn Reply> {
+    let body = ErrorResponse {
+        code: code.into(),
+        message: message.into(),
+    };
+    Box::new(reply::with_status(reply::json(&body), status))
+}
+
+/// Logs the error and makes an error response.
+fn failure_reply(context: &str, e: impl ToErrorResponse) -> Box<dyn Reply> {
+    let message = format!("{}: {}", context, e);
+    if e.status_code().is_server_error() {
+        error!("{}", message);
+    } else {
+        info!("{}", message);
+    }
+    error_reply(e.status_code(), e.error_code(), message)
+}
+
+/// Constructs `HomoService`s from the users, skipping invalid ones.
+/// Returns `RepositoryError::NotFound` if none remains.
+fn to_services<'a>(
+    users: impl IntoIterator<Item = &'a User>,
+) -> Result<Vec<HomoService>, RepositoryError> {
+    let services: Vec<_> = users
+        .into_iter()
+        .filter_map(|r| match HomoService::from_user(r) {
+            Ok(hs) => Some(hs),
+            Err(e) => {
+                warn!("Failed to construct HomoService: {}", e);
+                None
+            }
+        })
+        .collect();
+
+    if services.is_empty() {
+        Err(RepositoryError::NotFound("No such user".into()))
+    } else {
+        Ok(services)
+    }
+}
+
 /// Entrypoint of `GET /check`.
 pub async fn check_all(
     query: CheckQueryParameter,
@@ -30,14 +171,7 @@ pub async fn check_all(
 ) -> Result<Box<dyn Reply>, Infallible> {
     let users = match deps.repositories().user().fetch_all().await {
         Ok(users) => users,
-        Err(e) => {
-            let message = format!("Failed to fetch users: {}", e);
-            error!("{}", message);
-            return Ok(Box::new(reply::with_status(
-                message,
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )));
-        }
+        Err(e) => return Ok(failure_reply("Failed to fetch users", e)),
     };
 
     check_services(deps, users.iter(), query).await
@@ -57,14 +191,7 @@ pub async fn check_user(
         .await
     {
         Ok(users) => users,
-        Err(e) => {
-            let message = format!("Failed to fetch users: {}", e);
-            error!("{}", message);
-            return Ok(Box::new(reply::with_status(
-                message,
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )));
-        }
+        Err(e) => return Ok(failure_reply("Failed to fetch users", e)),
     };
 
     check_services(deps, users.iter(), query).await
@@ -76,31 +203,16 @@ async fn check_services(
     users: impl IntoIterator<Item = &User>,
     query: CheckQueryParameter,
 ) -> Result<Box<dyn Reply>, Infallible> {
-    let services: Vec<_> = users
-        .into_iter()
-        .map(|r| match HomoService::from_user(r) {
-            Ok(hs) => Some(hs),
-            Err(e) => {
-                warn!("Failed to construct HomoService: {}", e);
-                None
-            }
-        })
-        .flatten()
-        .collect();
-
-    if services.is_empty() {
-        // 0 件のときは 404 として扱う
-        return Ok(Box::new(reply::with_status(
-            "No such user",
-            StatusCode::NOT_FOUND,
-        )));
-    }
+    let services = match to_services(users) {
+        Ok(services) => services,
+        Err(e) => return Ok(failure_reply("Failed to find services", e)),
+    };
 
     match query.format {
         Some(CheckResponseFormat::ServerSentEvent) | None => {
-            check_services_sse(deps, services).await
+            check_services_sse(deps, services, query.fresh).await
         }
-        Some(CheckResponseFormat::Json) => check_services_json(deps, services).await,
+        Some(CheckResponseFormat::Json) => check_services_json(deps, services, query.fresh).await,
     }
 }
 
@@ -108,14 +220,17 @@ async fn check_services(
 async fn check_services_sse(
     deps: impl Container + 'static,
     services: Vec<HomoService>,
+    fresh: bool,
 ) -> Result<Box<dyn Reply>, Infallible> {
     let (tx, rx) = tokio_channel(64);
     let (service_sets, avatar_resolvers) = attach_avatar_resolver(services);
+    // クライアントが切断したら (rx が drop したら) 実行中のリクエストも中断する
+    let mut guard = TaskGuard::default();
 
     // avatar_url 解決
     for (provider, tx) in avatar_resolvers {
         let deps = deps.clone();
-        spawn(async move {
+        guard.spawn(async move {
             let avatar = fetch_avatar(deps, Arc::new(provider)).await;
             match tx.send(avatar) {
                 Ok(_) => (),
@@ -143,60 +258,51 @@ async fn check_services_sse(
         let service = Arc::new(service);
         let sender = tx.clone();
         let deps = deps.clone();
-        spawn(async move {
+        guard.spawn(async move {
             // アバター URL とリダイレクト結果は並行で
-            let (avatar_url, response) =
-                join!(resolver.recv(), request_service(deps, service.service_url.clone()));
-            let avatar_url = match avatar_url {
-                Ok(au) => au,
-                Err(_) => None,
-            };
-            let message = match response {
-                Ok(r) => (
-                    sse::event("response"),
-                    sse::json(CheckEventResponseData::build(
-                        &service,
-                        avatar_url.as_ref(),
-                        Some(&r),
-                    ))
-                    .into_b(),
-                ),
-                // error を受け取る仕様は本家クライアントにもあるけど
-                // 仕様が違うのでエラーでも response event を返す
-                // Err(e) => (sse::event("error"), sse::data(e).into_b().into_b()),
-                Err(_) => (
-                    sse::event("response"),
-                    sse::json(CheckEventResponseData::build(
-                        &service,
-                        avatar_url.as_ref(),
-                        None,
-                    ))
-                    .into_b(),
-                ),
-            };
-            // rx が drop してたら何もやることはない
-            match sender.clone().send(Ok(message)).await {
-                Ok(()) => (),
-                Err(_) => return,
-            }
+            let (avatar_url, (response, cached_at)) = join!(
+                resolver.recv(),
+                request_service_cached(deps, service.service_url.clone(), fresh)
+            );
+            let avatar_url = avatar_url.unwrap_or_default();
+            // error を受け取る仕様は本家クライアントにもあるけど
+            // 仕様が違うのでエラーでも response event を返す
+            let message = (
+                sse::event("response"),
+                sse::json(CheckEventResponseData::build(
+                    &service,
+                    avatar_url.as_ref(),
+                    &response,
+                    cached_at,
+                ))
+                .into_b(),
+            );
+            sender.clone().send(Ok(message)).await.ok();
         });
     }
 
-    Ok(Box::new(sse::reply(rx)))
+    let stream = GuardedStream {
+        stream: rx,
+        _guard: guard,
+    };
+    Ok(Box::new(sse::reply(stream)))
 }
 
 /// Checks given services and make SSE response.
 async fn check_services_json(
     deps: impl Container + 'static,
     services: Vec<HomoService>,
+    fresh: bool,
 ) -> Result<Box<dyn Reply>, Infallible> {
     let deps_chain = repeat(deps.clone());
     let (service_sets, avatar_resolvers) = attach_avatar_resolver(services);
+    // クライアントが切断したら (この Future が drop したら) アバター取得も中断する
+    let mut guard = TaskGuard::default();
 
     // avatar_url 解決
     for (provider, tx) in avatar_resolvers {
         let deps = deps.clone();
-        spawn(async move {
+        guard.spawn(async move {
             let avatar = fetch_avatar(deps, Arc::new(provider)).await;
             match tx.send(avatar) {
                 Ok(_) => (),
@@ -214,26 +320,14 @@ async fn check_services_json(
             .zip(deps_chain)
             .map(|((s, mut rx), deps)| async move {
                 let service = Arc::new(s);
-                let (avatar_url, response) =
-                    join!(rx.recv(), request_service(deps, service.service_url.clone()));
-                let avatar_url = match avatar_url {
-                    Ok(au) => au,
-                    Err(_) => None,
-                };
-                match response {
-                    Ok(response) => Some(CheckEventResponseData::build(
-                        &service,
-                        avatar_url.as_ref(),
-                        Some(&response),
-                    )),
-                    Err(_) => None,
-                }
+                let (avatar_url, (response, cached_at)) = join!(
+                    rx.recv(),
+                    request_service_cached(deps, service.service_url.clone(), fresh)
+                );
+                let avatar_url = avatar_url.unwrap_or_default();
+                CheckEventResponseData::build(&service, avatar_url.as_ref(), &response, cached_at)
             });
-    let results: Vec<_> = join_all(result_futures)
-        .await
-        .into_iter()
-        .flatten()
-        .collect();
+    let results = join_all(result_futures).await;
 
     Ok(Box::new(reply::json(&results)))
 }
@@ -245,14 +339,7 @@ pub async fn list_all(
 ) -> Result<Box<dyn Reply>, Infallible> {
     let users = match deps.repositories().user().fetch_all().await {
         Ok(users) => users,
-        Err(e) => {
-            let message = format!("Failed to fetch users: {}", e);
-            error!("{}", message);
-            return Ok(Box::new(reply::with_status(
-                message,
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )));
-        }
+        Err(e) => return Ok(failure_reply("Failed to fetch users", e)),
     };
 
     list_services(users.iter(), query).await
@@ -272,14 +359,7 @@ pub async fn list_user(
         .await
     {
         Ok(users) => users,
-        Err(e) => {
-            let message = format!("Failed to fetch users: {}", e);
-            error!("{}", message);
-            return Ok(Box::new(reply::with_status(
-                message,
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )));
-        }
+        Err(e) => return Ok(failure_reply("Failed to fetch users", e)),
     };
 
     list_services(users.iter(), query).await
@@ -290,25 +370,10 @@ async fn list_services(
     users: impl IntoIterator<Item = &User>,
     query: ListQueryParameter,
 ) -> Result<Box<dyn Reply>, Infallible> {
-    let services: Vec<_> = users
-        .into_iter()
-        .map(|r| match HomoService::from_user(r) {
-            Ok(hs) => Some(hs),
-            Err(e) => {
-                warn!("Failed to construct HomoService: {}", e);
-                None
-            }
-        })
-        .flatten()
-        .collect();
-
-    if services.is_empty() {
-        // 0 件のときは 404 として扱う
-        return Ok(Box::new(reply::with_status(
-            "No such user",
-            StatusCode::NOT_FOUND,
-        )));
-    }
+    let services = match to_services(users) {
+        Ok(services) => services,
+        Err(e) => return Ok(failure_reply("Failed to find services", e)),
+    };
 
     match query.format {
         Some(ListResponseFormat::Json) | None => {
@@ -331,20 +396,120 @@ async fn list_services(
     }
 }
 
+/// Entrypoint of `GET /history/:user`.
+pub async fn history_user(
+    screen_name: String,
+    query: HistoryQueryParameter,
+    deps: impl Container + 'static,
+) -> Result<Box<dyn Reply>, Infallible> {
+    // TODO: screen_name のバリデーション
+    let users = match deps
+        .repositories()
+        .user()
+        .fetch_by_screen_name(&screen_name)
+        .await
+    {
+        Ok(users) => users,
+        Err(e) => return Ok(failure_reply("Failed to fetch users", e)),
+    };
+
+    let service_urls: Vec<_> = match to_services(&users) {
+        Ok(services) => services.into_iter().map(|s| s.service_url).collect(),
+        Err(e) => return Ok(failure_reply("Failed to find services", e)),
+    };
+
+    let (page, per_page) = (query.page(), query.per_page());
+    let history = deps.repositories().check_history();
+    let (total, records) = join!(
+        history.count_by_urls(&service_urls),
+        history.fetch_by_urls(&service_urls, per_page, query.offset())
+    );
+    let (total, records) = match (total, records) {
+        (Ok(total), Ok(records)) => (total, records),
+        (Err(e), _) | (_, Err(e)) => return Ok(failure_reply("Failed to fetch check results", e)),
+    };
+
+    Ok(Box::new(reply::json(&HistoryJsonResponse {
+        page,
+        per_page,
+        total,
+        results: records
+            .iter()
+            .map(HistoryJsonResponseResult::build)
+            .collect(),
+    })))
+}
+
+/// Entrypoint of `GET /stats`.
+pub async fn stats_all(deps: impl Container + 'static) -> Result<Box<dyn Reply>, Infallible> {
+    let users = match deps.repositories().user().fetch_all().await {
+        Ok(users) => users,
+        Err(e) => return Ok(failure_reply("Failed to fetch users", e)),
+    };
+
+    stats_services(deps, users.iter()).await
+}
+
+/// Entrypoint of `GET /stats/:user`.
+pub async fn stats_user(
+    screen_name: String,
+    deps: impl Container + 'static,
+) -> Result<Box<dyn Reply>, Infallible> {
+    // TODO: screen_name のバリデーション
+    let users = match deps
+        .repositories()
+        .user()
+        .fetch_by_screen_name(&screen_name)
+        .await
+    {
+        Ok(users) => users,
+        Err(e) => return Ok(failure_reply("Failed to fetch users", e)),
+    };
+
+    stats_services(deps, users.iter()).await
+}
+
+/// Reports the statistics of given services.
+async fn stats_services(
+    deps: impl Container + 'static,
+    users: impl IntoIterator<Item = &User>,
+) -> Result<Box<dyn Reply>, Infallible> {
+    let services = match to_services(users) {
+        Ok(services) => services,
+        Err(e) => return Ok(failure_reply("Failed to find services", e)),
+    };
+
+    let service_urls: Vec<_> = services.iter().map(|s| s.service_url.clone()).collect();
+    let windows = match summarize_services(deps, &service_urls).await {
+        Ok(windows) => windows,
+        Err(e) => return Ok(failure_reply("Failed to aggregate check results", e)),
+    };
+
+    let json: Vec<_> = services
+        .iter()
+        .map(|service| StatsJsonResponse {
+            homo: ListJsonResponse::build(service),
+            windows: windows
+                .iter()
+                .map(|(window, summaries)| {
+                    let summary = summaries
+                        .iter()
+                        .find(|s| s.service_url == service.service_url);
+                    StatsJsonResponseWindow::build(window, summary)
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(Box::new(reply::json(&json)))
+}
+
 pub async fn redirect_badge(
     _query: JsonValue,
     deps: impl Container + 'static,
 ) -> Result<Box<dyn Reply>, Infallible> {
     let count = match deps.repositories().user().count_all().await {
         Ok(c) => c,
-        Err(e) => {
-            let message = format!("Failed to fetch users count: {}", e);
-            error!("{}", message);
-            return Ok(Box::new(reply::with_status(
-                message,
-                StatusCode::INTERNAL_SERVER_ERROR,
-            )));
-        }
+        Err(e) => return Ok(failure_reply("Failed to fetch users count", e)),
     };
 
     let mut url = Url::parse("https://img.shields.io").unwrap();
@@ -353,3 +518,29 @@ pub async fn redirect_badge(
     let uri = Uri::from_str(&url[..]).unwrap();
     Ok(Box::new(redirect::redirect(uri)))
 }
+
+/// Entrypoint of `GET /metrics`.
+/// Exposes counters in the Prometheus text format.
+pub async fn metrics(deps: impl Container + 'static) -> Result<Box<dyn Reply>, Infallible> {
+    let flights = deps.flights();
+    let body = format!(
+        "# HELP homochecker_checks_started_total Checks actually requested.\n\
+         # TYPE homochecker_checks_started_total counter\n\
+         homochecker_checks_started_total {}\n\
+         # HELP homochecker_checks_coalesced_total Checks which shared the result of an in-flight one.\n\
+         # TYPE homochecker_checks_coalesced_total counter\n\
+         homochecker_checks_coalesced_total {}\n\
+         # HELP homochecker_checks_in_flight Services being checked.\n\
+         # TYPE homochecker_checks_in_flight gauge\n\
+         homochecker_checks_in_flight {}\n",
+        flights.started(),
+        flights.coalesced(),
+        flights.in_flight(),
+    );
+
+    Ok(Box::new(reply::with_header(
+        body,
+        "Content-Type",
+        "text/plain; version=0.0.4",
+    )))
+}
@@ -1,6 +1,11 @@
-use crate::domain::{HomoService, HomoServiceResponse, HomoServiceStatus, Provider};
-use std::error::Error;
+use crate::{
+    config::StatsWindow,
+    domain::{HomoService, HomoServiceError, HomoServiceResponse, Provider},
+    repository::{CheckRecord, CheckSummary},
+};
+use std::{collections::BTreeMap, error::Error};
 
+use chrono::{DateTime, Utc};
 use idna::domain_to_unicode;
 use serde::{Deserialize, Serialize};
 use url::{Host, Position, Url};
@@ -18,6 +23,8 @@ pub enum CheckResponseFormat {
 #[derive(Debug, Deserialize)]
 pub struct CheckQueryParameter {
     pub format: Option<CheckResponseFormat>,
+    #[serde(default)]
+    pub fresh: bool,
 }
 
 /// Response format for `GET /list/*`.
@@ -35,6 +42,43 @@ pub struct ListQueryParameter {
     pub format: Option<ListResponseFormat>,
 }
 
+/// Represents a data object of query parameter of `GET /history/*`.
+#[derive(Debug, Deserialize)]
+pub struct HistoryQueryParameter {
+    pub page: Option<usize>,
+    pub per_page: Option<usize>,
+}
+
+impl HistoryQueryParameter {
+    /// The number of records per page if not specified.
+    pub const DEFAULT_PER_PAGE: usize = 20;
+
+    /// The maximum number of records per page.
+    pub const MAX_PER_PAGE: usize = 100;
+
+    /// The maximum page number. Larger numbers are treated as this.
+    pub const MAX_PAGE: usize = 1_000_000;
+
+    /// Returns the page number, starting from 1.
+    pub fn page(&self) -> usize {
+        self.page
+            .unwrap_or(1)
+            .clamp(1, HistoryQueryParameter::MAX_PAGE)
+    }
+
+    /// Returns the number of records per page.
+    pub fn per_page(&self) -> usize {
+        self.per_page
+            .unwrap_or(HistoryQueryParameter::DEFAULT_PER_PAGE)
+            .clamp(1, HistoryQueryParameter::MAX_PER_PAGE)
+    }
+
+    /// Returns the number of records before the page.
+    pub fn offset(&self) -> usize {
+        (self.page() - 1) * self.per_page()
+    }
+}
+
 /// Represents a data object of 'initialize' event in `GET /check`.
 #[derive(Debug, Serialize)]
 pub struct CheckEventInitializeData {
@@ -52,6 +96,57 @@ pub struct CheckEventResponseDataHomo {
     pub secure: bool,
 }
 
+/// Represents `detail` property of the data object of 'response' event in `GET /check`.
+#[derive(Debug, Serialize)]
+pub struct CheckEventResponseDataDetail {
+    pub validator: String,
+    pub matched: Option<String>,
+    pub offset: Option<usize>,
+}
+
+/// Represents an element of `redirects` property of the data object of 'response' event in `GET /check`.
+#[derive(Debug, Serialize)]
+pub struct CheckEventResponseDataRedirect {
+    pub url: String,
+    pub status: u16,
+    pub location: Option<String>,
+    pub duration: f64,
+}
+
+/// Represents `timing` property of the data object of 'response' event in `GET /check`.
+#[derive(Debug, Serialize)]
+pub struct CheckEventResponseDataTiming {
+    pub dns: f64,
+    pub connect: f64,
+    pub tls: Option<f64>,
+    pub ttfb: f64,
+    pub body: f64,
+}
+
+/// Represents `error` property of the data object of 'response' event in `GET /check`.
+#[derive(Debug, Serialize)]
+pub struct CheckEventResponseDataError {
+    pub kind: String,
+    pub message: String,
+}
+
+/// Represents `dual_stack` property of the data object of 'response' event in `GET /check`.
+#[derive(Debug, Serialize)]
+pub struct CheckEventResponseDataDualStack {
+    pub ipv4: CheckEventResponseDataStack,
+    pub ipv6: CheckEventResponseDataStack,
+}
+
+/// Represents the result over an IP version in `dual_stack` property.
+#[derive(Debug, Serialize)]
+pub struct CheckEventResponseDataStack {
+    pub status: String,
+    pub ip: Option<String>,
+    pub duration: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CheckEventResponseDataError>,
+}
+
 /// Represents a data object of 'response' event in `GET /check`.
 #[derive(Debug, Serialize)]
 pub struct CheckEventResponseData {
@@ -59,6 +154,19 @@ pub struct CheckEventResponseData {
     pub status: String,
     pub ip: Option<String>,
     pub duration: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<CheckEventResponseDataDetail>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<CheckEventResponseDataRedirect>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<CheckEventResponseDataError>,
+    pub attempts: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timing: Option<CheckEventResponseDataTiming>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dual_stack: Option<CheckEventResponseDataDualStack>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_at: Option<DateTime<Utc>>,
 }
 
 /// Represents a response object of `GET /list/*`.
@@ -71,6 +179,60 @@ pub struct ListJsonResponse {
     pub secure: bool,
 }
 
+/// Represents an element of `results` property of the response object of `GET /history/*`.
+#[derive(Debug, Serialize)]
+pub struct HistoryJsonResponseResult {
+    pub url: String,
+    pub checked_at: DateTime<Utc>,
+    pub status: String,
+    pub ip: Option<String>,
+    pub duration: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Represents a response object of `GET /history/*`.
+#[derive(Debug, Serialize)]
+pub struct HistoryJsonResponse {
+    pub page: usize,
+    pub per_page: usize,
+    pub total: usize,
+    pub results: Vec<HistoryJsonResponseResult>,
+}
+
+/// Represents `latency` property of an element of `windows` property in `GET /stats/*`.
+#[derive(Debug, Serialize)]
+pub struct StatsJsonResponseLatency {
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+/// Represents an element of `windows` property of the response object of `GET /stats/*`.
+#[derive(Debug, Serialize)]
+pub struct StatsJsonResponseWindow {
+    pub window: String,
+    pub checks: usize,
+    pub uptime: Option<f64>,
+    pub validity: Option<f64>,
+    pub latency: Option<StatsJsonResponseLatency>,
+    pub statuses: BTreeMap<String, usize>,
+}
+
+/// Represents a response object of `GET /stats/*`.
+#[derive(Debug, Serialize)]
+pub struct StatsJsonResponse {
+    pub homo: ListJsonResponse,
+    pub windows: Vec<StatsJsonResponseWindow>,
+}
+
+/// Represents a response object of errors.
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String,
+}
+
 /// It can be converted into display URL.
 trait ToDisplayUrl {
     type Error;
@@ -104,7 +266,8 @@ impl CheckEventResponseData {
     pub fn build(
         service: &HomoService,
         avatar_url: Option<&Url>,
-        response: Option<&HomoServiceResponse>,
+        response: &HomoServiceResponse,
+        cached_at: Option<DateTime<Utc>>,
     ) -> CheckEventResponseData {
         // TODO: display_ur; を整形
         CheckEventResponseData {
@@ -123,23 +286,69 @@ impl CheckEventResponseData {
                     .unwrap_or_else(|_| "".into()),
                 secure: service.service_url.scheme() == "https",
             },
-            status: response
-                .map(|s| match s.status {
-                    HomoServiceStatus::RedirectResponse | HomoServiceStatus::RedirectContent => {
-                        "OK"
-                    }
-                    HomoServiceStatus::LinkContent => "CONTAINS",
-                    HomoServiceStatus::Invalid => "WRONG",
-                    HomoServiceStatus::Error => "ERROR",
+            status: response.status.as_str().into(),
+            ip: response.remote_address.map(|addr| addr.ip().to_string()),
+            duration: response.duration.as_secs_f64(),
+            detail: response
+                .evidence
+                .as_ref()
+                .map(|evidence| CheckEventResponseDataDetail {
+                    validator: evidence.validator.clone(),
+                    matched: evidence.matched.clone(),
+                    offset: evidence.offset,
+                }),
+            redirects: response
+                .redirects
+                .iter()
+                .map(|hop| CheckEventResponseDataRedirect {
+                    url: hop.url.to_string(),
+                    status: hop.status.as_u16(),
+                    location: hop.location.clone(),
+                    duration: hop.duration.as_secs_f64(),
                 })
-                .unwrap_or("ERROR")
-                .into(),
-            ip: response
-                .map(|res| res.remote_address.map(|addr| addr.ip().to_string()))
-                .flatten(),
-            duration: response
-                .map(|res| res.duration.as_secs_f64())
-                .unwrap_or(0.0),
+                .collect(),
+            error: response
+                .error
+                .as_ref()
+                .map(CheckEventResponseDataError::build),
+            attempts: response.attempts,
+            timing: response.timing.map(|timing| CheckEventResponseDataTiming {
+                dns: timing.dns.as_secs_f64(),
+                connect: timing.connect.as_secs_f64(),
+                tls: timing.tls.map(|tls| tls.as_secs_f64()),
+                ttfb: timing.ttfb.as_secs_f64(),
+                body: timing.body.as_secs_f64(),
+            }),
+            dual_stack: response.dual_stack.as_ref().map(|dual_stack| {
+                CheckEventResponseDataDualStack {
+                    ipv4: CheckEventResponseDataStack::build(&dual_stack.ipv4),
+                    ipv6: CheckEventResponseDataStack::build(&dual_stack.ipv6),
+                }
+            }),
+            cached_at,
+        }
+    }
+}
+
+impl CheckEventResponseDataStack {
+    pub fn build(response: &HomoServiceResponse) -> CheckEventResponseDataStack {
+        CheckEventResponseDataStack {
+            status: response.status.as_str().into(),
+            ip: response.remote_address.map(|addr| addr.ip().to_string()),
+            duration: response.duration.as_secs_f64(),
+            error: response
+                .error
+                .as_ref()
+                .map(CheckEventResponseDataError::build),
+        }
+    }
+}
+
+impl CheckEventResponseDataError {
+    pub fn build(error: &HomoServiceError) -> CheckEventResponseDataError {
+        CheckEventResponseDataError {
+            kind: error.kind.as_str().into(),
+            message: error.message.clone(),
         }
     }
 }
@@ -162,3 +371,40 @@ impl ListJsonResponse {
         }
     }
 }
+
+impl HistoryJsonResponseResult {
+    pub fn build(record: &CheckRecord) -> HistoryJsonResponseResult {
+        HistoryJsonResponseResult {
+            url: record.service_url.to_string(),
+            checked_at: record.checked_at,
+            status: record.status.clone(),
+            ip: record.ip.map(|ip| ip.to_string()),
+            duration: record.duration.as_secs_f64(),
+            error: record.error.map(|kind| kind.as_str().into()),
+        }
+    }
+}
+
+impl StatsJsonResponseWindow {
+    pub fn build(window: &StatsWindow, summary: Option<&CheckSummary>) -> StatsJsonResponseWindow {
+        let checks = summary.map_or(0, |s| s.checks);
+        StatsJsonResponseWindow {
+            window: window.name.clone(),
+            checks,
+            uptime: summary
+                .filter(|s| s.checks > 0)
+                .map(|s| s.up as f64 / s.checks as f64 * 100.0),
+            validity: summary
+                .filter(|s| s.checks > 0)
+                .map(|s| s.valid() as f64 / s.checks as f64 * 100.0),
+            latency: summary
+                .and_then(|s| s.latency)
+                .map(|latency| StatsJsonResponseLatency {
+                    p50: latency.p50.as_secs_f64(),
+                    p95: latency.p95.as_secs_f64(),
+                    p99: latency.p99.as_secs_f64(),
+                }),
+            statuses: summary.map(|s| s.statuses.clone()).unwrap_or_default(),
+        }
+    }
+}
@@ -14,7 +14,11 @@ pub fn homochecker(
         .or(homochecker_check_user(repo.clone()))
         .or(homochecker_list_all(repo.clone()))
         .or(homochecker_list_user(repo.clone()))
-        .or(homochecker_badge(repo))
+        .or(homochecker_history_user(repo.clone()))
+        .or(homochecker_stats_all(repo.clone()))
+        .or(homochecker_stats_user(repo.clone()))
+        .or(homochecker_badge(repo.clone()))
+        .or(homochecker_metrics(repo))
         .with(warp::log("homochecker_rs"))
 }
 
@@ -69,6 +73,37 @@ fn homochecker_list_user(
         .and_then(action::list_user)
 }
 
+/// Returns the filter of `GET /history/:user`.
+fn homochecker_history_user(
+    repo: impl Container + 'static,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("history" / String)
+        .and(warp::get())
+        .and(warp::query())
+        .and(attach_pool(repo))
+        .and_then(action::history_user)
+}
+
+/// Returns the filter of `GET /stats`.
+fn homochecker_stats_all(
+    repo: impl Container + 'static,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("stats")
+        .and(warp::get())
+        .and(attach_pool(repo))
+        .and_then(action::stats_all)
+}
+
+/// Returns the filter of `GET /stats/:user`.
+fn homochecker_stats_user(
+    repo: impl Container + 'static,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("stats" / String)
+        .and(warp::get())
+        .and(attach_pool(repo))
+        .and_then(action::stats_user)
+}
+
 /// Returns the filter of `GET /badge`.
 fn homochecker_badge(
     repo: impl Container + 'static,
@@ -79,3 +114,13 @@ fn homochecker_badge(
         .and(attach_pool(repo))
         .and_then(action::redirect_badge)
 }
+
+/// Returns the filter of `GET /metrics`.
+fn homochecker_metrics(
+    repo: impl Container + 'static,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .and(attach_pool(repo))
+        .and_then(action::metrics)
+}
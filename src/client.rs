@@ -0,0 +1,414 @@
+//! Contains the HTTP client which measures each phase of requests.
+//!
+//! This speaks HTTP/1.1 over hyper's connection API instead of using reqwest, because reqwest 0.10
+//! neither accepts a custom connector nor resolver. Checking requires control over each address:
+//! the egress policy is checked on the resolved addresses and the connection goes to the checked
+//! one, the limiter counts requests per address, and DNS, connect, TLS and TTFB are timed separately.
+//! The body is read by chunks so that validators can stop it early.
+//!
+//! Consequently some features of reqwest are not supported on purpose:
+//! * HTTP/2, since a connection is made per request and the response is read once.
+//! * Proxies, since the egress policy must see the final destination.
+//! * Compression, since `Accept-Encoding` is not sent and bodies are read as they are.
+//! * Redirects, which `HomoRequestService` follows hop by hop to record and limit them.
+
+use crate::{
+    domain::{HomoServiceError, HomoServiceErrorKind, HttpResponse, IpVersion, RequestTiming},
+    egress::EgressPolicy,
+    service::{RequestLimiter, RequestPermit, ServiceError},
+    validation::{response::ValidatorPipeline, ResponseContent},
+};
+use std::{
+    collections::HashMap,
+    future::Future,
+    io::{Error as IoError, ErrorKind as IoErrorKind},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use hyper::{
+    body::HttpBody,
+    client::conn::handshake,
+    header::{ACCEPT, HOST},
+    http::response::Parts,
+    Body, Error as HyperError, Request,
+};
+use log::debug;
+use native_tls::TlsConnector as NativeTlsConnector;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{lookup_host, TcpStream},
+    spawn,
+    time::timeout,
+};
+use tokio_tls::TlsConnector;
+use url::{Host, Position, Url};
+
+/// The maximum size of response bodies by default.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Sends GET requests without following redirects.
+#[derive(Clone)]
+pub struct HttpClient {
+    tls: TlsConnector,
+    timeout: Duration,
+    hosts: HashMap<String, Vec<IpAddr>>,
+    egress: EgressPolicy,
+    max_body_size: usize,
+    validators: Option<ValidatorPipeline>,
+    limiter: Option<Arc<RequestLimiter>>,
+}
+
+impl HttpClient {
+    /// Constructs with the timeout of each request.
+    pub fn new(timeout: Duration) -> Result<HttpClient, ServiceError> {
+        let tls = NativeTlsConnector::new().map_err(|e| ServiceError::Unavailable(e.into()))?;
+        Ok(HttpClient {
+            tls: TlsConnector::from(tls),
+            timeout,
+            hosts: HashMap::new(),
+            egress: EgressPolicy::default(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            validators: None,
+            limiter: None,
+        })
+    }
+
+    /// Replaces the maximum size of response bodies.
+    /// The rest of larger bodies is not read.
+    pub fn with_max_body_size(mut self, max_body_size: usize) -> HttpClient {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Stops reading response bodies once the validators decide the status.
+    pub fn with_validators(mut self, validators: ValidatorPipeline) -> HttpClient {
+        self.validators = Some(validators);
+        self
+    }
+
+    /// Limits in-flight requests per destination address, including each hop of redirects.
+    /// The time waiting for the limiter is not counted in the timeout.
+    pub fn with_limiter(mut self, limiter: Arc<RequestLimiter>) -> HttpClient {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Replaces the policy of destinations.
+    pub fn with_egress(mut self, egress: EgressPolicy) -> HttpClient {
+        self.egress = egress;
+        self
+    }
+
+    /// Resolves the host to given addresses instead of DNS, like `/etc/hosts`.
+    pub fn with_host(
+        mut self,
+        host: &str,
+        addresses: impl IntoIterator<Item = IpAddr>,
+    ) -> HttpClient {
+        self.hosts
+            .insert(host.to_ascii_lowercase(), addresses.into_iter().collect());
+        self
+    }
+
+    /// Sends a GET request.
+    /// If `version` is specified, only addresses of the IP version are used.
+    pub async fn fetch(
+        &self,
+        url: &Url,
+        version: Option<IpVersion>,
+    ) -> Result<HttpResponse, ServiceError> {
+        let started = Instant::now();
+        let mut timing = RequestTiming::default();
+
+        let remaining = self.remaining(started, &timing);
+        let (domain, addresses) =
+            within(url, remaining, self.resolve_url(url, version, &mut timing)).await?;
+        let (stream, _permit) = self.connect(url, &addresses, &mut timing, started).await?;
+        let remaining = self.remaining(started, &timing);
+        within(url, remaining, self.exchange(url, &domain, stream, timing)).await
+    }
+
+    /// Returns the rest of the timeout. The time waiting for the limiter is not counted.
+    fn remaining(&self, started: Instant, timing: &RequestTiming) -> Duration {
+        (self.timeout + timing.queue)
+            .checked_sub(started.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// Resolves the host of the URL to the allowed addresses.
+    async fn resolve_url(
+        &self,
+        url: &Url,
+        version: Option<IpVersion>,
+        timing: &mut RequestTiming,
+    ) -> Result<(String, Vec<SocketAddr>), ServiceError> {
+        self.egress
+            .check_url(url)
+            .map_err(|message| HomoServiceError::new(HomoServiceErrorKind::Blocked, message))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+
+        // 名前解決
+        let started = Instant::now();
+        let (domain, addresses) = match url.host() {
+            Some(Host::Domain(domain)) => (domain.to_owned(), self.resolve(domain, port).await?),
+            Some(Host::Ipv4(ip)) => (ip.to_string(), vec![SocketAddr::new(ip.into(), port)]),
+            Some(Host::Ipv6(ip)) => (ip.to_string(), vec![SocketAddr::new(ip.into(), port)]),
+            None => {
+                let message = format!("No host in {}", url);
+                return Err(ServiceError::InvalidData(message.into()));
+            }
+        };
+        let addresses: Vec<_> = match version {
+            Some(version) => addresses
+                .into_iter()
+                .filter(|address| IpVersion::of(address.ip()) == version)
+                .collect(),
+            None => addresses,
+        };
+        timing.dns = started.elapsed();
+
+        // 許可されていない宛先には接続しない
+        let mut blocked = None;
+        let addresses: Vec<_> = addresses
+            .into_iter()
+            .filter(
+                |address| match self.egress.check_destination(address.ip(), address.port()) {
+                    Ok(()) => true,
+                    Err(message) => {
+                        debug!("Blocked {} ({}): {}", url, address, message);
+                        blocked = Some(message);
+                        false
+                    }
+                },
+            )
+            .collect();
+        if let (true, Some(message)) = (addresses.is_empty(), blocked) {
+            return Err(HomoServiceError::new(HomoServiceErrorKind::Blocked, message).into());
+        }
+
+        Ok((domain, addresses))
+    }
+
+    /// Connects to the first reachable address.
+    /// Each attempt is given an even share of the remaining time, so unreachable addresses
+    /// do not consume the whole timeout.
+    /// Waits for the limiter before each attempt, and the permit is held until the response is read.
+    async fn connect(
+        &self,
+        url: &Url,
+        addresses: &[SocketAddr],
+        timing: &mut RequestTiming,
+        started: Instant,
+    ) -> Result<(TcpStream, Option<RequestPermit<'_>>), ServiceError> {
+        let connecting = Instant::now();
+        let queued = timing.queue;
+        let mut last_error = None;
+        for (index, address) in addresses.iter().enumerate() {
+            let waiting = Instant::now();
+            let permit = match &self.limiter {
+                Some(limiter) => Some(limiter.acquire(address.ip()).await),
+                None => None,
+            };
+            timing.queue += waiting.elapsed();
+
+            // 応答しないアドレスで時間を使い切らないように、残りのアドレスで等分する
+            let attempts = (addresses.len() - index) as u32;
+            let remaining = self.remaining(started, timing) / attempts;
+            match timeout(remaining, TcpStream::connect(address)).await {
+                Ok(Ok(stream)) => {
+                    timing.connect = connecting.elapsed() - (timing.queue - queued);
+                    return Ok((stream, permit));
+                }
+                Ok(Err(e)) => last_error = Some(e),
+                Err(_) if attempts == 1 => return Err(timed_out(url)),
+                Err(_) => {
+                    debug!("Connection to {} ({}) timed out", url, address);
+                    let message = format!("Connection to {} timed out", address);
+                    last_error = Some(IoError::new(IoErrorKind::TimedOut, message));
+                }
+            }
+        }
+
+        let error = match last_error {
+            Some(e) => {
+                let kind = match e.kind() {
+                    IoErrorKind::ConnectionRefused => HomoServiceErrorKind::ConnectionRefused,
+                    IoErrorKind::TimedOut => HomoServiceErrorKind::Timeout,
+                    _ => HomoServiceErrorKind::Connection,
+                };
+                HomoServiceError::new(kind, e.to_string())
+            }
+            None => HomoServiceError::new(HomoServiceErrorKind::Dns, "No address found"),
+        };
+        Err(error.into())
+    }
+
+    /// Sends the request on the connected stream and reads the response.
+    async fn exchange(
+        &self,
+        url: &Url,
+        domain: &str,
+        stream: TcpStream,
+        mut timing: RequestTiming,
+    ) -> Result<HttpResponse, ServiceError> {
+        let remote_address = stream.peer_addr().ok();
+        let request = Request::get(&url[Position::BeforePath..Position::AfterQuery])
+            .header(HOST, &url[Position::BeforeHost..Position::BeforePath])
+            .header(ACCEPT, "*/*")
+            .body(Body::empty())
+            .map_err(|e| ServiceError::InvalidData(e.into()))?;
+        let (parts, mut body) = match url.scheme() {
+            "https" => {
+                let started = Instant::now();
+                let stream =
+                    self.tls.connect(domain, stream).await.map_err(|e| {
+                        HomoServiceError::new(HomoServiceErrorKind::Tls, e.to_string())
+                    })?;
+                timing.tls = Some(started.elapsed());
+                send(stream, request, &mut timing).await?
+            }
+            "http" => send(stream, request, &mut timing).await?,
+            otherwise => {
+                let message = format!("Unsupported scheme: {}", otherwise);
+                return Err(ServiceError::InvalidData(message.into()));
+            }
+        };
+
+        // 非 ASCII の値 (Cookie など) で検査全体を失敗させないよう、不正な部分は置き換える
+        let headers = parts
+            .headers
+            .iter()
+            .map(|(k, v)| {
+                let value = String::from_utf8_lossy(v.as_bytes()).into_owned();
+                (k.as_str().to_owned(), value)
+            })
+            .collect();
+        let mut response = HttpResponse {
+            url: url.clone(),
+            status: parts.status,
+            remote_address,
+            headers,
+            body: vec![],
+            redirects: vec![],
+            timing: None,
+            judgment: None,
+        };
+
+        // 判定が確定した時点で読むのをやめる
+        // 検証器は読んだ分だけをそれぞれ前回の続きから見るので、本文を何度も検証し直さない
+        let started = Instant::now();
+        let mut scanning = self
+            .validators
+            .as_ref()
+            .map(|validators| (validators.scan(), ResponseContent::new(&response)));
+        let mut judgment = match &mut scanning {
+            Some((scan, content)) => scan.judge(&response, content).await,
+            None => None,
+        };
+        while judgment.is_none() {
+            let chunk = match body.data().await {
+                Some(chunk) => chunk.map_err(|e| {
+                    HomoServiceError::new(HomoServiceErrorKind::BodyDecode, e.to_string())
+                })?,
+                None => break,
+            };
+            let remaining = self.max_body_size - response.body.len();
+            let truncated = chunk.len() >= remaining;
+            let chunk = &chunk[..chunk.len().min(remaining)];
+            response.body.extend_from_slice(chunk);
+            if let Some((scan, content)) = &mut scanning {
+                content.feed(chunk);
+                judgment = scan.judge(&response, content).await;
+            }
+            if truncated {
+                debug!("Truncated {} at {} bytes", url, self.max_body_size);
+                break;
+            }
+        }
+
+        // 途中で確定しなければ、読んだ分を本文全体として判定する
+        match (&judgment, &mut scanning) {
+            (Some(_), _) => debug!("Stopped reading {} at {} bytes", url, response.body.len()),
+            (None, Some((scan, content))) => {
+                content.finish();
+                judgment = scan.judge(&response, content).await;
+            }
+            (None, None) => (),
+        }
+        timing.body = started.elapsed();
+
+        response.judgment = judgment;
+        response.timing = Some(timing);
+        Ok(response)
+    }
+
+    /// Resolves the domain.
+    async fn resolve(&self, domain: &str, port: u16) -> Result<Vec<SocketAddr>, HomoServiceError> {
+        if let Some(addresses) = self.hosts.get(&domain.to_ascii_lowercase()) {
+            return Ok(addresses
+                .iter()
+                .map(|ip| SocketAddr::new(*ip, port))
+                .collect());
+        }
+
+        let addresses = lookup_host((domain, port))
+            .await
+            .map_err(|e| HomoServiceError::new(HomoServiceErrorKind::Dns, e.to_string()))?;
+        Ok(addresses.collect())
+    }
+}
+
+/// Runs the phase of the request within the remaining time.
+async fn within<T>(
+    url: &Url,
+    remaining: Duration,
+    phase: impl Future<Output = Result<T, ServiceError>>,
+) -> Result<T, ServiceError> {
+    timeout(remaining, phase)
+        .await
+        .unwrap_or_else(|_| Err(timed_out(url)))
+}
+
+/// Returns the error of the timed out request.
+fn timed_out(url: &Url) -> ServiceError {
+    let message = format!("Request to {} timed out", url);
+    HomoServiceError::new(HomoServiceErrorKind::Timeout, message).into()
+}
+
+/// Sends the request on the stream and receives the response header.
+async fn send<S>(
+    stream: S,
+    request: Request<Body>,
+    timing: &mut RequestTiming,
+) -> Result<(Parts, Body), HomoServiceError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = handshake(stream).await.map_err(classify_hyper_error)?;
+    spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Connection error: {}", e);
+        }
+    });
+
+    let started = Instant::now();
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(classify_hyper_error)?;
+    timing.ttfb = started.elapsed();
+
+    Ok(response.into_parts())
+}
+
+/// Classifies the error in HTTP exchange.
+fn classify_hyper_error(error: HyperError) -> HomoServiceError {
+    let kind = if error.is_parse() {
+        HomoServiceErrorKind::Unknown
+    } else {
+        HomoServiceErrorKind::Connection
+    };
+    HomoServiceError::new(kind, error.to_string())
+}
@@ -0,0 +1,231 @@
+//! Contains the runtime configuration.
+
+use crate::{
+    client::DEFAULT_MAX_BODY_SIZE,
+    domain::HomoServiceErrorKind,
+    egress::EgressPolicy,
+    validation::{
+        response::{ValidatorPipeline, DEFAULT_VALIDATORS},
+        TargetUrls,
+    },
+};
+use std::{
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+
+/// The maximum delay before a retry.
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Represents the configuration loaded at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The URLs which homo services should point to.
+    pub target_urls: Arc<TargetUrls>,
+
+    /// The validators to determine `HomoServiceStatus`.
+    pub validators: ValidatorPipeline,
+
+    /// The options for requests to homo services.
+    pub request: RequestConfig,
+
+    /// The policy to retry failed checks.
+    pub retry: RetryConfig,
+
+    /// The options for caching check results.
+    pub cache: CacheConfig,
+
+    /// The options for statistics of check results.
+    pub stats: StatsConfig,
+}
+
+/// Represents the options for requests to homo services.
+#[derive(Debug, Clone)]
+pub struct RequestConfig {
+    /// The timeout of each request, including reading the body.
+    pub timeout: Duration,
+
+    /// The maximum size of response bodies. The rest is not read nor validated.
+    pub max_body_size: usize,
+
+    /// The maximum number of redirects to follow.
+    pub max_redirects: usize,
+
+    /// The maximum number of cross-domain redirects to follow.
+    /// `0` disables following them.
+    pub max_cross_domain_redirects: usize,
+
+    /// The maximum number of in-flight requests.
+    pub max_concurrency: usize,
+
+    /// The maximum number of in-flight requests to the same IP address.
+    pub max_concurrency_per_address: usize,
+
+    /// Whether to check over IPv4 and IPv6 separately.
+    pub dual_stack: bool,
+
+    /// The policy of destinations.
+    pub egress: EgressPolicy,
+}
+
+/// Represents the options for caching check results.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// How long check results are cached. `0` disables caching.
+    pub ttl: Duration,
+}
+
+/// Represents the options for statistics of check results.
+#[derive(Debug, Clone)]
+pub struct StatsConfig {
+    /// The periods to aggregate, counted back from now.
+    pub windows: Vec<StatsWindow>,
+}
+
+/// Represents a period to aggregate, such as `24h` or `7d`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsWindow {
+    /// The name as configured.
+    pub name: String,
+
+    /// The length of the period.
+    pub duration: Duration,
+}
+
+/// Represents the policy to retry failed checks.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// The maximum number of attempts. `1` disables retrying.
+    pub attempts: usize,
+
+    /// The delay before the first retry. It doubles on each retry.
+    pub backoff: Duration,
+
+    /// The error kinds to retry.
+    pub retryable: Vec<HomoServiceErrorKind>,
+}
+
+impl RetryConfig {
+    /// Returns the delay before the retry following given attempt (1-origin).
+    /// The delay is capped at `MAX_RETRY_DELAY`.
+    pub fn delay(&self, attempt: usize) -> Duration {
+        // 倍率も積も桁あふれしないように頭打ちにする
+        let factor = 1 << attempt.saturating_sub(1).min(16);
+        self.backoff
+            .checked_mul(factor)
+            .unwrap_or(MAX_RETRY_DELAY)
+            .min(MAX_RETRY_DELAY)
+    }
+
+    /// Checks whether the error kind is retryable.
+    pub fn is_retryable(&self, kind: HomoServiceErrorKind) -> bool {
+        self.retryable.contains(&kind)
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            attempts: 1,
+            backoff: Duration::from_millis(500),
+            retryable: vec![
+                HomoServiceErrorKind::ConnectionRefused,
+                HomoServiceErrorKind::Connection,
+                HomoServiceErrorKind::Timeout,
+                HomoServiceErrorKind::HttpServerError,
+            ],
+        }
+    }
+}
+
+impl StatsWindow {
+    /// Returns the start of the window ending at `now`.
+    /// Windows reaching before the epoch start at the epoch.
+    pub fn since(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let epoch = DateTime::from(UNIX_EPOCH);
+        ChronoDuration::from_std(self.duration)
+            .ok()
+            .and_then(|window| now.checked_sub_signed(window))
+            .map(|since| since.max(epoch))
+            .unwrap_or(epoch)
+    }
+}
+
+impl FromStr for StatsWindow {
+    type Err = String;
+
+    /// Parses a positive number followed by `s`, `m`, `h` or `d`.
+    fn from_str(s: &str) -> Result<StatsWindow, String> {
+        let s = s.trim();
+        let (amount, unit) = s.split_at(s.len() - s.chars().last().map_or(0, char::len_utf8));
+        let seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "d" => 24 * 60 * 60,
+            _ => return Err(format!("Unknown unit of window: {}", s)),
+        };
+        match amount.parse::<u32>() {
+            Ok(amount) if amount > 0 => Ok(StatsWindow {
+                name: s.into(),
+                duration: Duration::from_secs(u64::from(amount) * seconds),
+            }),
+            _ => Err(format!("Invalid window: {}", s)),
+        }
+    }
+}
+
+impl Default for StatsConfig {
+    fn default() -> StatsConfig {
+        StatsConfig {
+            windows: ["24h", "7d", "30d"]
+                .iter()
+                .map(|window| window.parse().unwrap())
+                .collect(),
+        }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig {
+        CacheConfig {
+            ttl: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Default for RequestConfig {
+    fn default() -> RequestConfig {
+        RequestConfig {
+            timeout: Duration::from_secs(5),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            max_redirects: 10,
+            max_cross_domain_redirects: 0,
+            max_concurrency: 32,
+            max_concurrency_per_address: 4,
+            dual_stack: false,
+            egress: EgressPolicy::default(),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        let target_urls = Arc::new(TargetUrls::default());
+        let validators =
+            ValidatorPipeline::from_names(DEFAULT_VALIDATORS.iter().copied(), target_urls.clone())
+                .expect("Default validators must be valid");
+
+        Config {
+            target_urls,
+            validators,
+            request: RequestConfig::default(),
+            retry: RetryConfig::default(),
+            cache: CacheConfig::default(),
+            stats: StatsConfig::default(),
+        }
+    }
+}
@@ -1,12 +1,25 @@
 //! Contains abstract domain model.
 
-use crate::repository::User;
-use std::{collections::HashMap, error::Error, net::SocketAddr, time::Duration};
+use crate::{repository::User, validation::HtmlDocument};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
 
-use log::warn;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
 use http::StatusCode;
+use log::warn;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+/// The number of bytes at the head of HTML in which `<meta>` charset is looked for.
+pub(crate) const CHARSET_PRESCAN_SIZE: usize = 1024;
+
 /// Represents a person who provides the homo service.
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub enum Provider {
@@ -28,7 +41,7 @@ pub struct HomoService {
 }
 
 /// Represents the status of the homo service.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum HomoServiceStatus {
     /// The service returned a 301/302/308 response with specific `Location` header.
     RedirectResponse,
@@ -36,18 +49,91 @@ pub enum HomoServiceStatus {
     /// The service returned a successful response which contains redirect meta element.
     RedirectContent,
 
-    /// The service returned a successful response which contains just specific URL(s).
+    /// The service returned a successful response which contains redirect script.
+    RedirectScript,
+
+    /// The service redirected to another domain, which redirected to specific URL.
+    Indirect,
+
+    /// The service returned a successful response which declares specific URL as canonical.
+    CanonicalContent,
+
+    /// The service returned a successful response which contains links to specific URL(s).
     LinkContent,
 
+    /// The service returned a successful response which just mentions specific URL(s)
+    /// outside of links, such as in texts, comments or scripts.
+    MentionOnly,
+
     /// The service returned a successful response which contains no valid URL.
     Invalid,
 
-    /// The service returned an error.
+    /// The service redirected to a URL which had already been visited.
+    RedirectLoop,
+
+    /// The service redirected more times than allowed.
+    TooManyRedirects,
+
+    /// The request to the service failed. See `HomoServiceError` for details.
     Error,
+
+    /// The request to the service was refused by `EgressPolicy`.
+    Blocked,
+}
+
+/// Represents the kind of failures in requests to homo services.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HomoServiceErrorKind {
+    /// The host name could not be resolved.
+    Dns,
+
+    /// The connection was refused.
+    ConnectionRefused,
+
+    /// The connection failed for other reasons.
+    Connection,
+
+    /// The TLS handshake failed, such as by an invalid certificate.
+    Tls,
+
+    /// The request timed out.
+    Timeout,
+
+    /// The response body could not be read.
+    BodyDecode,
+
+    /// The service returned a 4xx response.
+    HttpClientError,
+
+    /// The service returned a 5xx response.
+    HttpServerError,
+
+    /// The destination was not allowed by `EgressPolicy`.
+    Blocked,
+
+    /// Other failures.
+    Unknown,
+}
+
+/// Represents a failure in the request to homo service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HomoServiceError {
+    /// The kind of this failure.
+    pub kind: HomoServiceErrorKind,
+
+    /// The human-readable description.
+    pub message: String,
+}
+
+/// Represents the version of IP.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum IpVersion {
+    V4,
+    V6,
 }
 
 /// Represents the response information of homo service.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct HomoServiceResponse {
     /// Status.
     pub status: HomoServiceStatus,
@@ -57,11 +143,104 @@ pub struct HomoServiceResponse {
 
     /// The response time.
     pub duration: Duration,
+
+    /// The evidence of the status. `None` if no validator decided it.
+    pub evidence: Option<HomoServiceEvidence>,
+
+    /// The redirects followed before the final response.
+    pub redirects: Vec<RedirectHop>,
+
+    /// The failure. `Some` only if the status is `HomoServiceStatus::Error` or `Blocked`.
+    pub error: Option<HomoServiceError>,
+
+    /// The number of attempts, including retries.
+    pub attempts: usize,
+
+    /// The timing breakdown of the final response. `None` if not measured.
+    pub timing: Option<RequestTiming>,
+
+    /// The results checked over IPv4 and IPv6 separately. `None` unless dual-stack mode.
+    pub dual_stack: Option<Box<DualStackResponse>>,
+}
+
+/// Represents the results of a homo service checked over each IP version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DualStackResponse {
+    /// The result over IPv4.
+    pub ipv4: HomoServiceResponse,
+
+    /// The result over IPv6.
+    pub ipv6: HomoServiceResponse,
+}
+
+/// Represents the time spent in each phase of a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestTiming {
+    /// Waiting for the concurrency limit. Not included in the other phases.
+    pub queue: Duration,
+
+    /// Name resolution.
+    pub dns: Duration,
+
+    /// TCP connection.
+    pub connect: Duration,
+
+    /// TLS handshake. `None` for plain HTTP.
+    pub tls: Option<Duration>,
+
+    /// From sending the request to receiving the response header.
+    pub ttfb: Duration,
+
+    /// Reading the response body.
+    pub body: Duration,
+}
+
+/// Represents the evidence on which `HomoServiceStatus` was decided.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HomoServiceEvidence {
+    /// The name of the validator which decided the status.
+    pub validator: String,
+
+    /// The matched part, such as `Location` value or meta element attribute.
+    pub matched: Option<String>,
+
+    /// The byte offset of the matched part in the decoded body.
+    pub offset: Option<usize>,
+}
+
+/// Represents a redirect response followed during the request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedirectHop {
+    /// The requested URL.
+    pub url: Url,
+
+    /// Response status code.
+    #[serde(with = "status_code")]
+    pub status: StatusCode,
+
+    /// The raw `Location` header.
+    pub location: Option<String>,
+
+    /// The response time of this hop.
+    pub duration: Duration,
+}
+
+/// Represents an error which stops following redirects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectError {
+    /// The redirect pointed to a URL which had already been visited.
+    Loop(Vec<RedirectHop>),
+
+    /// The number of redirects exceeded the limit.
+    TooMany(Vec<RedirectHop>),
 }
 
 /// Represents an abstract HTTP response.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HttpResponse {
+    /// The URL of this response, after redirects were followed.
+    pub url: Url,
+
     /// The remote socket address.
     pub remote_address: Option<SocketAddr>,
 
@@ -71,8 +250,18 @@ pub struct HttpResponse {
     /// Response headers. Header names must be lowercase.
     pub headers: HashMap<String, String>,
 
-    /// Response body.
-    pub body: String,
+    /// Raw response body. Use `HttpResponse::text` to get decoded one.
+    pub body: Vec<u8>,
+
+    /// The redirects followed before this response.
+    pub redirects: Vec<RedirectHop>,
+
+    /// The timing breakdown. `None` if not measured.
+    pub timing: Option<RequestTiming>,
+
+    /// The status and its evidence decided by validators while the body was read.
+    /// `None` if not validated.
+    pub judgment: Option<(HomoServiceStatus, Option<HomoServiceEvidence>)>,
 }
 
 impl Provider {
@@ -81,7 +270,7 @@ impl Provider {
         let parts: Vec<_> = entity_sn.split('@').collect();
         match parts.len() {
             1 => Ok(Provider::Twitter(parts[0].into())),
-            3 if parts[0] == "" => Ok(Provider::Mastodon {
+            3 if parts[0].is_empty() => Ok(Provider::Mastodon {
                 screen_name: parts[1].into(),
                 domain: parts[2].into(),
             }),
@@ -124,6 +313,196 @@ impl HomoService {
     }
 }
 
+impl HttpResponse {
+    /// Returns the essence of `Content-Type` in lowercase, such as `text/html`.
+    pub fn mime_type(&self) -> Option<String> {
+        let content_type = self.headers.get("content-type")?;
+        let essence = content_type.split(';').next()?.trim();
+        if essence.is_empty() {
+            None
+        } else {
+            Some(essence.to_ascii_lowercase())
+        }
+    }
+
+    /// Checks whether the body is supposed to be a text.
+    /// Returns `true` if `Content-Type` is not specified.
+    pub fn is_text(&self) -> bool {
+        match self.mime_type() {
+            Some(mime) => {
+                mime.starts_with("text/")
+                    || mime.ends_with("+xml")
+                    || mime.ends_with("+json")
+                    || mime == "application/xml"
+                    || mime == "application/json"
+                    || mime == "application/javascript"
+            }
+            None => true,
+        }
+    }
+
+    /// Decodes the body.
+    /// The encoding is determined by BOM, `Content-Type` charset and `<meta>` charset in order.
+    /// Returns `None` if the body is not a text.
+    pub fn text(&self) -> Option<Cow<'_, str>> {
+        if !self.is_text() {
+            return None;
+        }
+
+        let encoding = match self.header_charset() {
+            Some(encoding) => encoding,
+            None if self.is_html() => meta_charset(&self.body).unwrap_or(UTF_8),
+            None => UTF_8,
+        };
+        // BOM は decode の中で優先される
+        let (decoded, _, _) = encoding.decode(&self.body);
+        Some(decoded)
+    }
+
+    /// Checks whether the body is supposed to be HTML.
+    /// Returns `true` if `Content-Type` is not specified.
+    pub fn is_html(&self) -> bool {
+        matches!(
+            self.mime_type().as_deref(),
+            Some("text/html") | Some("application/xhtml+xml") | None
+        )
+    }
+
+    /// Returns the encoding specified in `Content-Type` header.
+    pub(crate) fn header_charset(&self) -> Option<&'static Encoding> {
+        let content_type = self.headers.get("content-type")?;
+        content_type.split(';').skip(1).find_map(|param| {
+            let (name, value) = param.split_at(param.find('=')?);
+            if name.trim().eq_ignore_ascii_case("charset") {
+                let label = value[1..].trim().trim_matches(&['"', '\''][..]);
+                Encoding::for_label(label.as_bytes())
+            } else {
+                None
+            }
+        })
+    }
+}
+
+impl RedirectError {
+    /// Returns the redirects followed before the error.
+    pub fn redirects(&self) -> &[RedirectHop] {
+        match self {
+            RedirectError::Loop(hops) | RedirectError::TooMany(hops) => hops,
+        }
+    }
+}
+
+impl Display for RedirectError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            RedirectError::Loop(hops) => write!(f, "Redirect loop after {} hop(s)", hops.len()),
+            RedirectError::TooMany(hops) => write!(f, "Too many redirects ({} hops)", hops.len()),
+        }
+    }
+}
+
+impl Error for RedirectError {}
+
+impl HomoServiceStatus {
+    /// Returns the name used in API, such as `OK` or `WRONG`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HomoServiceStatus::RedirectResponse | HomoServiceStatus::RedirectContent => "OK",
+            HomoServiceStatus::RedirectScript => "SCRIPT",
+            HomoServiceStatus::Indirect => "INDIRECT",
+            HomoServiceStatus::CanonicalContent => "CANONICAL",
+            HomoServiceStatus::LinkContent => "CONTAINS",
+            HomoServiceStatus::MentionOnly => "MENTION",
+            HomoServiceStatus::Invalid => "WRONG",
+            HomoServiceStatus::RedirectLoop => "LOOP",
+            HomoServiceStatus::TooManyRedirects => "TOO_MANY_REDIRECTS",
+            HomoServiceStatus::Error => "ERROR",
+            HomoServiceStatus::Blocked => "BLOCKED",
+        }
+    }
+}
+
+impl IpVersion {
+    /// Returns the version of the address.
+    pub fn of(address: IpAddr) -> IpVersion {
+        match address {
+            IpAddr::V4(_) => IpVersion::V4,
+            IpAddr::V6(_) => IpVersion::V6,
+        }
+    }
+}
+
+impl HomoServiceErrorKind {
+    /// Returns the machine-readable name, such as `CONNECTION_REFUSED`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HomoServiceErrorKind::Dns => "DNS",
+            HomoServiceErrorKind::ConnectionRefused => "CONNECTION_REFUSED",
+            HomoServiceErrorKind::Connection => "CONNECTION",
+            HomoServiceErrorKind::Tls => "TLS",
+            HomoServiceErrorKind::Timeout => "TIMEOUT",
+            HomoServiceErrorKind::BodyDecode => "BODY_DECODE",
+            HomoServiceErrorKind::HttpClientError => "HTTP_CLIENT_ERROR",
+            HomoServiceErrorKind::HttpServerError => "HTTP_SERVER_ERROR",
+            HomoServiceErrorKind::Blocked => "BLOCKED",
+            HomoServiceErrorKind::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+impl FromStr for HomoServiceErrorKind {
+    type Err = String;
+
+    /// Parses the name returned by `as_str`, ignoring case.
+    fn from_str(s: &str) -> Result<HomoServiceErrorKind, String> {
+        let kind = match s.trim().to_ascii_uppercase().as_str() {
+            "DNS" => HomoServiceErrorKind::Dns,
+            "CONNECTION_REFUSED" => HomoServiceErrorKind::ConnectionRefused,
+            "CONNECTION" => HomoServiceErrorKind::Connection,
+            "TLS" => HomoServiceErrorKind::Tls,
+            "TIMEOUT" => HomoServiceErrorKind::Timeout,
+            "BODY_DECODE" => HomoServiceErrorKind::BodyDecode,
+            "HTTP_CLIENT_ERROR" => HomoServiceErrorKind::HttpClientError,
+            "HTTP_SERVER_ERROR" => HomoServiceErrorKind::HttpServerError,
+            "BLOCKED" => HomoServiceErrorKind::Blocked,
+            "UNKNOWN" => HomoServiceErrorKind::Unknown,
+            _ => return Err(format!("Unknown error kind: {}", s)),
+        };
+        Ok(kind)
+    }
+}
+
+impl HomoServiceError {
+    /// Constructs with the kind and the message.
+    pub fn new(kind: HomoServiceErrorKind, message: impl Into<String>) -> HomoServiceError {
+        HomoServiceError {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    /// Constructs from the status code of 4xx/5xx response.
+    /// Returns `None` for other status codes.
+    pub fn from_status(status: StatusCode) -> Option<HomoServiceError> {
+        let kind = if status.is_client_error() {
+            HomoServiceErrorKind::HttpClientError
+        } else if status.is_server_error() {
+            HomoServiceErrorKind::HttpServerError
+        } else {
+            return None;
+        };
+        Some(HomoServiceError::new(kind, format!("HTTP {}", status)))
+    }
+}
+
+impl Display for HomoServiceError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}: {}", self.kind.as_str(), self.message)
+    }
+}
+
+impl Error for HomoServiceError {}
+
 /// An extention trait provides `unwrap_or_warn`.
 pub trait UnwrapOrWarnExt {
     type Output;
@@ -145,3 +524,47 @@ impl<T, E: Error> UnwrapOrWarnExt for Result<T, E> {
     }
 }
 
+/// Serializes `StatusCode` as a number.
+mod status_code {
+    use http::StatusCode;
+    use serde::{de::Error as DeError, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(status: &StatusCode, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(status.as_u16())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<StatusCode, D::Error> {
+        let code = u16::deserialize(deserializer)?;
+        StatusCode::from_u16(code).map_err(DeError::custom)
+    }
+}
+
+/// Returns the encoding specified in `<meta>` in the head of HTML body.
+/// `body` may be the head of the body being read.
+pub(crate) fn meta_charset(body: &[u8]) -> Option<&'static Encoding> {
+    // windows-1252 は全バイトを ASCII 互換で読めるので prescan に使える
+    let head = &body[..body.len().min(CHARSET_PRESCAN_SIZE)];
+    let (head, _, _) = WINDOWS_1252.decode(head);
+    let document = HtmlDocument::parse(&head);
+    let encoding = document.tags_named("meta").find_map(|meta| {
+        if let Some(charset) = meta.attribute("charset") {
+            return Encoding::for_label(charset.trim().as_bytes());
+        }
+        let http_equiv = meta.attribute("http-equiv")?;
+        if !http_equiv.trim().eq_ignore_ascii_case("content-type") {
+            return None;
+        }
+        let content = meta.attribute("content")?.to_ascii_lowercase();
+        let label = content[content.find("charset")? + 7..]
+            .trim_start()
+            .strip_prefix('=')?
+            .trim_start()
+            .split(|c: char| c == ';' || c.is_ascii_whitespace())
+            .next()?
+            .trim_matches(&['"', '\''][..]);
+        Encoding::for_label(label.as_bytes())
+    })?;
+
+    // UTF-16 の指定は UTF-8 として扱う
+    Some(encoding.output_encoding())
+}
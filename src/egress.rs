@@ -0,0 +1,182 @@
+//! Contains the policy for outbound requests to homo services.
+
+use std::{
+    fmt::{Display, Formatter, Result as FmtResult},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use lazy_static::lazy_static;
+use url::Url;
+
+lazy_static! {
+    /// The ranges which are not reachable from the Internet (RFC 6890 and so on).
+    static ref NON_GLOBAL_RANGES: Vec<IpRange> = [
+        // IPv4
+        "0.0.0.0/8",       // This network
+        "10.0.0.0/8",      // Private
+        "100.64.0.0/10",   // Shared address space
+        "127.0.0.0/8",     // Loopback
+        "169.254.0.0/16",  // Link-local
+        "172.16.0.0/12",   // Private
+        "192.0.0.0/24",    // IETF protocol assignments
+        "192.0.2.0/24",    // Documentation
+        "192.168.0.0/16",  // Private
+        "198.18.0.0/15",   // Benchmarking
+        "198.51.100.0/24", // Documentation
+        "203.0.113.0/24",  // Documentation
+        "224.0.0.0/4",     // Multicast
+        "240.0.0.0/4",     // Reserved and broadcast
+        // IPv6
+        "::/128",          // Unspecified
+        "::1/128",         // Loopback
+        "::/96",           // IPv4-compatible (deprecated)
+        "64:ff9b:1::/48",  // Local-use IPv4/IPv6 translation
+        "100::/64",        // Discard-only
+        "2001:db8::/32",   // Documentation
+        "2002::/16",       // 6to4
+        "fc00::/7",        // Unique local
+        "fe80::/10",       // Link-local
+        "fec0::/10",       // Site-local (deprecated)
+        "ff00::/8",        // Multicast
+    ]
+    .iter()
+    .map(|range| range.parse().unwrap())
+    .collect();
+}
+
+/// Represents a range of IP addresses in CIDR notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    /// Checks whether the range contains the address.
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    /// Parses `address/prefix`. A bare address is treated as a single address.
+    fn from_str(s: &str) -> Result<IpRange, String> {
+        let s = s.trim();
+        let (address, prefix) = match s.find('/') {
+            Some(index) => (&s[..index], Some(&s[(index + 1)..])),
+            None => (s, None),
+        };
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("Invalid IP address: {}", s))?;
+        let max_prefix = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(prefix) => match prefix.parse() {
+                Ok(prefix) if prefix <= max_prefix => prefix,
+                _ => return Err(format!("Invalid prefix length: {}", s)),
+            },
+            None => max_prefix,
+        };
+
+        Ok(IpRange { address, prefix })
+    }
+}
+
+impl Display for IpRange {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}/{}", self.address, self.prefix)
+    }
+}
+
+/// Represents the policy which destinations requests may go to.
+#[derive(Debug, Clone)]
+pub struct EgressPolicy {
+    /// The allowed URL schemes.
+    pub allowed_schemes: Vec<String>,
+
+    /// The allowed destination ports.
+    pub allowed_ports: Vec<u16>,
+
+    /// The addresses which are allowed regardless of other rules.
+    pub allowlist: Vec<IpRange>,
+}
+
+impl EgressPolicy {
+    /// Checks the URL before name resolution.
+    pub fn check_url(&self, url: &Url) -> Result<(), String> {
+        if !self.allowed_schemes.iter().any(|s| s == url.scheme()) {
+            return Err(format!("Scheme {} is not allowed", url.scheme()));
+        }
+        Ok(())
+    }
+
+    /// Checks the destination after name resolution.
+    pub fn check_destination(&self, address: IpAddr, port: u16) -> Result<(), String> {
+        if self.allowlist.iter().any(|range| range.contains(address)) {
+            return Ok(());
+        }
+        if !self.allowed_ports.contains(&port) {
+            return Err(format!("Port {} is not allowed", port));
+        }
+        if !is_global(address) {
+            return Err(format!("Address {} is not global", address));
+        }
+        Ok(())
+    }
+}
+
+impl Default for EgressPolicy {
+    fn default() -> EgressPolicy {
+        EgressPolicy {
+            allowed_schemes: vec!["http".into(), "https".into()],
+            allowed_ports: vec![80, 443],
+            allowlist: vec![],
+        }
+    }
+}
+
+/// Checks whether the address is reachable from the Internet.
+pub fn is_global(address: IpAddr) -> bool {
+    // IPv4 射影アドレスと NAT64 のアドレスは、埋め込まれた IPv4 アドレスで判定する
+    let address = match address {
+        IpAddr::V6(v6) => match to_embedded_ipv4(v6) {
+            Some(v4) => IpAddr::V4(v4),
+            None => address,
+        },
+        IpAddr::V4(_) => address,
+    };
+    !NON_GLOBAL_RANGES
+        .iter()
+        .any(|range| range.contains(address))
+}
+
+/// Extracts the IPv4 address from `::ffff:a.b.c.d` or `64:ff9b::a.b.c.d`.
+fn to_embedded_ipv4(address: Ipv6Addr) -> Option<Ipv4Addr> {
+    match address.octets() {
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, a, b, c, d]
+        | [0, 0x64, 0xff, 0x9b, 0, 0, 0, 0, 0, 0, 0, 0, a, b, c, d] => {
+            Some(Ipv4Addr::new(a, b, c, d))
+        }
+        _ => None,
+    }
+}
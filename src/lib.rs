@@ -2,12 +2,16 @@
 
 pub mod action;
 pub mod api;
+pub mod client;
+pub mod config;
 pub mod domain;
+pub mod egress;
 pub mod repository;
 pub mod service;
 pub mod validation;
 
-use self::{repository::Repositories, service::Services};
+use self::{action::CheckFlights, config::Config, repository::Repositories, service::Services};
+use std::sync::Arc;
 
 /// Represents the container of dependencies.
 pub trait Container
@@ -25,4 +29,10 @@ where
 
     /// Returns services.
     fn services(&self) -> Self::Services;
+
+    /// Returns the configuration.
+    fn config(&self) -> Arc<Config>;
+
+    /// Returns the checks in flight, which are shared by concurrent requests.
+    fn flights(&self) -> Arc<CheckFlights>;
 }
@@ -1,8 +1,17 @@
 mod adapter;
 
 use crate::adapter::{Container, Repositories, Services};
-use homochecker_rs::api::route::homochecker;
-use std::{collections::HashMap, env::vars, net::SocketAddr, process::exit};
+use homochecker_rs::{
+    api::route::homochecker,
+    config::{CacheConfig, Config, RequestConfig, RetryConfig, StatsConfig},
+    validation::{
+        response::{ValidatorPipeline, DEFAULT_VALIDATORS},
+        TargetUrls,
+    },
+};
+use std::{
+    collections::HashMap, env::vars, net::SocketAddr, process::exit, sync::Arc, time::Duration,
+};
 
 use dotenv::dotenv;
 use log::{error, info};
@@ -64,10 +73,190 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             exit(1);
         });
 
-    let container = Container::new(Repositories::new(pg_client, redis), Services::new());
+    // 対象 URL
+    let target_urls = match envs.get("TARGET_URLS") {
+        Some(list) => list.parse().unwrap_or_else(|e| {
+            error!("Failed to parse `TARGET_URLS`: {}", e);
+            exit(1);
+        }),
+        None => TargetUrls::default(),
+    };
+    let target_urls = Arc::new(target_urls);
+
+    // バリデーター
+    let validator_names: Vec<_> = match envs.get("VALIDATORS") {
+        Some(list) => list.split(',').collect(),
+        None => DEFAULT_VALIDATORS.to_vec(),
+    };
+    let validators = ValidatorPipeline::from_names(validator_names, target_urls.clone())
+        .unwrap_or_else(|e| {
+            error!("Failed to parse `VALIDATORS`: {}", e);
+            exit(1);
+        });
+    info!("Validators: {}", validators.names().join(", "));
+
+    // リクエスト
+    let mut request = RequestConfig::default();
+    if let Some(timeout) = envs.get("REQUEST_TIMEOUT") {
+        let millis = parse_positive(timeout).unwrap_or_else(|e| {
+            error!("Failed to parse `REQUEST_TIMEOUT`: {}", e);
+            exit(1);
+        });
+        request.timeout = Duration::from_millis(millis as u64);
+    }
+    if let Some(max) = envs.get("MAX_BODY_SIZE") {
+        request.max_body_size = parse_positive(max).unwrap_or_else(|e| {
+            error!("Failed to parse `MAX_BODY_SIZE`: {}", e);
+            exit(1);
+        });
+    }
+    if let Some(max) = envs.get("MAX_REDIRECTS") {
+        request.max_redirects = max.parse().unwrap_or_else(|e| {
+            error!("Failed to parse `MAX_REDIRECTS`: {}", e);
+            exit(1);
+        });
+    }
+    if let Some(max) = envs.get("MAX_CROSS_DOMAIN_REDIRECTS") {
+        request.max_cross_domain_redirects = max.parse().unwrap_or_else(|e| {
+            error!("Failed to parse `MAX_CROSS_DOMAIN_REDIRECTS`: {}", e);
+            exit(1);
+        });
+    }
+    if let Some(enabled) = envs.get("DUAL_STACK") {
+        request.dual_stack = enabled.parse().unwrap_or_else(|e| {
+            error!("Failed to parse `DUAL_STACK`: {}", e);
+            exit(1);
+        });
+    }
+    if let Some(list) = envs.get("ALLOWED_SCHEMES") {
+        request.egress.allowed_schemes = list
+            .split(',')
+            .map(|scheme| scheme.trim().to_ascii_lowercase())
+            .filter(|scheme| !scheme.is_empty())
+            .collect();
+    }
+    if let Some(list) = envs.get("ALLOWED_PORTS") {
+        request.egress.allowed_ports = list
+            .split(',')
+            .filter(|port| !port.trim().is_empty())
+            .map(|port| port.trim().parse())
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                error!("Failed to parse `ALLOWED_PORTS`: {}", e);
+                exit(1);
+            });
+    }
+    if let Some(list) = envs.get("EGRESS_ALLOWLIST") {
+        request.egress.allowlist = list
+            .split(',')
+            .filter(|range| !range.trim().is_empty())
+            .map(|range| range.parse())
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                error!("Failed to parse `EGRESS_ALLOWLIST`: {}", e);
+                exit(1);
+            });
+        let ranges: Vec<_> = request
+            .egress
+            .allowlist
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        info!("Egress allowlist: {}", ranges.join(", "));
+    }
+    if let Some(max) = envs.get("MAX_CONCURRENCY") {
+        request.max_concurrency = parse_positive(max).unwrap_or_else(|e| {
+            error!("Failed to parse `MAX_CONCURRENCY`: {}", e);
+            exit(1);
+        });
+    }
+    if let Some(max) = envs.get("MAX_CONCURRENCY_PER_ADDRESS") {
+        request.max_concurrency_per_address = parse_positive(max).unwrap_or_else(|e| {
+            error!("Failed to parse `MAX_CONCURRENCY_PER_ADDRESS`: {}", e);
+            exit(1);
+        });
+    }
+
+    // リトライ
+    let mut retry = RetryConfig::default();
+    if let Some(attempts) = envs.get("RETRY_ATTEMPTS") {
+        retry.attempts = parse_positive(attempts).unwrap_or_else(|e| {
+            error!("Failed to parse `RETRY_ATTEMPTS`: {}", e);
+            exit(1);
+        });
+    }
+    if let Some(backoff) = envs.get("RETRY_BACKOFF") {
+        let millis = backoff.parse().unwrap_or_else(|e| {
+            error!("Failed to parse `RETRY_BACKOFF`: {}", e);
+            exit(1);
+        });
+        retry.backoff = Duration::from_millis(millis);
+    }
+    if let Some(list) = envs.get("RETRY_ON") {
+        retry.retryable = list
+            .split(',')
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| name.parse())
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                error!("Failed to parse `RETRY_ON`: {}", e);
+                exit(1);
+            });
+    }
+
+    // キャッシュ
+    let mut cache = CacheConfig::default();
+    if let Some(ttl) = envs.get("CHECK_CACHE_TTL") {
+        let secs = ttl.parse().unwrap_or_else(|e| {
+            error!("Failed to parse `CHECK_CACHE_TTL`: {}", e);
+            exit(1);
+        });
+        cache.ttl = Duration::from_secs(secs);
+    }
+    let mut repositories = Repositories::new(pg_client, redis);
+    match envs.get("CHECK_CACHE").map(|s| &s[..]) {
+        Some("redis") | None => (),
+        Some("memory") => repositories = repositories.with_memory_check_cache(),
+        Some(otherwise) => {
+            error!("Unknown `CHECK_CACHE`: {}", otherwise);
+            exit(1);
+        }
+    }
+
+    // 統計
+    let mut stats = StatsConfig::default();
+    if let Some(list) = envs.get("STATS_WINDOWS") {
+        stats.windows = list
+            .split(',')
+            .filter(|window| !window.trim().is_empty())
+            .map(|window| window.parse())
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                error!("Failed to parse `STATS_WINDOWS`: {}", e);
+                exit(1);
+            });
+    }
+
+    let config = Config {
+        target_urls,
+        validators,
+        request,
+        retry,
+        cache,
+        stats,
+    };
+    let container = Container::new(repositories, Services::new(&config), config);
     let routes = homochecker(container);
 
     info!("Listening on {}", listen_address);
     warp::serve(routes).run(listen_address).await;
     Ok(())
 }
+
+/// Parses a positive integer.
+fn parse_positive(value: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    match value.parse()? {
+        0 => Err("must be positive".into()),
+        n => Ok(n),
+    }
+}
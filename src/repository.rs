@@ -1,13 +1,34 @@
 //! Contains data repository.
 
-use crate::domain::Provider;
-use std::{time::Duration, error::Error};
+use crate::domain::{HomoServiceErrorKind, HomoServiceResponse, Provider};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    net::IpAddr,
+    time::Duration,
+};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use url::Url;
 
-/// Various error types in repository operations.
-pub type RepositoryError = Box<dyn Error + Send + Sync>;
+/// Represents errors in repository operations.
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// The requested record does not exist.
+    NotFound(String),
+
+    /// The backend could not be reached.
+    Unavailable(Box<dyn Error + Send + Sync>),
+
+    /// The stored data could not be converted.
+    InvalidData(Box<dyn Error + Send + Sync>),
+
+    /// The backend returned an error.
+    Upstream(Box<dyn Error + Send + Sync>),
+}
 
 /// Represents a record of `users`.
 #[derive(Debug, Clone, Default)]
@@ -18,6 +39,160 @@ pub struct User {
     pub url: String,
 }
 
+/// Represents a cached result of checking a homo service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedCheckResult {
+    /// The result.
+    pub response: HomoServiceResponse,
+
+    /// When the result was cached.
+    pub cached_at: DateTime<Utc>,
+}
+
+/// Represents a record of `check_results`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckRecord {
+    /// The URL of the service.
+    pub service_url: Url,
+
+    /// When the service was checked.
+    pub checked_at: DateTime<Utc>,
+
+    /// The status name, such as `OK` or `WRONG`.
+    pub status: String,
+
+    /// The address of the service.
+    pub ip: Option<IpAddr>,
+
+    /// The time taken to check.
+    pub duration: Duration,
+
+    /// The kind of the error if failed.
+    pub error: Option<HomoServiceErrorKind>,
+}
+
+impl CheckRecord {
+    /// Builds from the result of checking the service.
+    pub fn from_response(
+        service_url: &Url,
+        response: &HomoServiceResponse,
+        checked_at: DateTime<Utc>,
+    ) -> CheckRecord {
+        CheckRecord {
+            service_url: service_url.clone(),
+            checked_at,
+            status: response.status.as_str().into(),
+            ip: response.remote_address.map(|addr| addr.ip()),
+            duration: response.duration,
+            error: response.error.as_ref().map(|e| e.kind),
+        }
+    }
+}
+
+/// The statuses counted as down in `CheckSummary`, that is, the service was not reachable.
+pub const DOWN_STATUSES: &[&str] = &["ERROR", "BLOCKED"];
+
+/// The statuses counted as valid in `CheckSummary`, that is, the service pointed to the targets.
+pub const VALID_STATUSES: &[&str] = &[
+    "OK",
+    "SCRIPT",
+    "INDIRECT",
+    "CANONICAL",
+    "CONTAINS",
+    "MENTION",
+];
+
+/// Checks whether the status is in `DOWN_STATUSES`.
+pub fn is_down(status: &str) -> bool {
+    DOWN_STATUSES.contains(&status)
+}
+
+/// Represents the percentiles of the time taken to check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyPercentiles {
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+impl LatencyPercentiles {
+    /// Calculates from the durations. `None` if empty.
+    /// Each percentile is interpolated between the nearest durations, like `percentile_cont` in SQL.
+    pub fn of(mut durations: Vec<Duration>) -> Option<LatencyPercentiles> {
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort();
+        Some(LatencyPercentiles {
+            p50: percentile(&durations, 0.5),
+            p95: percentile(&durations, 0.95),
+            p99: percentile(&durations, 0.99),
+        })
+    }
+}
+
+/// Returns the percentile of the sorted non-empty durations, where `fraction` is in `0.0..=1.0`.
+fn percentile(sorted: &[Duration], fraction: f64) -> Duration {
+    let position = fraction * (sorted.len() - 1) as f64;
+    let lower = sorted[position.floor() as usize];
+    let upper = sorted[position.ceil() as usize];
+    lower + (upper - lower).mul_f64(position.fract())
+}
+
+/// Represents the aggregated records of a service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckSummary {
+    /// The URL of the service.
+    pub service_url: Url,
+
+    /// The number of checks.
+    pub checks: usize,
+
+    /// The number of checks whose status is not in `DOWN_STATUSES`.
+    pub up: usize,
+
+    /// The latency of the checks counted in `up`.
+    pub latency: Option<LatencyPercentiles>,
+
+    /// The number of checks by status name.
+    pub statuses: BTreeMap<String, usize>,
+}
+
+impl CheckSummary {
+    /// Aggregates the statuses and the durations of checks of the service.
+    pub fn summarize(
+        service_url: Url,
+        checks: impl IntoIterator<Item = (String, Duration)>,
+    ) -> CheckSummary {
+        let mut statuses: BTreeMap<String, usize> = BTreeMap::new();
+        let mut durations = vec![];
+        for (status, duration) in checks {
+            // レイテンシは応答があったものだけで計算する
+            if !is_down(&status) {
+                durations.push(duration);
+            }
+            *statuses.entry(status).or_default() += 1;
+        }
+
+        CheckSummary {
+            service_url,
+            checks: statuses.values().sum(),
+            up: durations.len(),
+            latency: LatencyPercentiles::of(durations),
+            statuses,
+        }
+    }
+
+    /// Returns the number of checks whose status is in `VALID_STATUSES`.
+    /// Unlike `up`, broken services such as `WRONG` or `LOOP` are not counted.
+    pub fn valid(&self) -> usize {
+        VALID_STATUSES
+            .iter()
+            .filter_map(|status| self.statuses.get(*status))
+            .sum()
+    }
+}
+
 /// Represents the container which includes repositories.
 pub trait Repositories
 where
@@ -29,11 +204,23 @@ where
     /// The actual type for `UrlRepository`.
     type Avatar: AvatarRepository;
 
+    /// The actual type for `CheckResultRepository`.
+    type CheckResult: CheckResultRepository;
+
+    /// The actual type for `CheckHistoryRepository`.
+    type CheckHistory: CheckHistoryRepository;
+
     /// Returns user repository.
     fn user(&self) -> Self::User;
 
     /// Returns URL repository.
     fn avatar(&self) -> Self::Avatar;
+
+    /// Returns check result repository.
+    fn check_result(&self) -> Self::CheckResult;
+
+    /// Returns check history repository.
+    fn check_history(&self) -> Self::CheckHistory;
 }
 
 /// It can fetch users.
@@ -52,6 +239,35 @@ where
     async fn fetch_by_screen_name(&self, screen_name: &str) -> Result<Vec<User>, RepositoryError>;
 }
 
+/// It can record and fetch the past check results.
+#[async_trait]
+pub trait CheckHistoryRepository
+where
+    Self: Sized + Clone + Send + Sync,
+{
+    /// Inserts a record into `check_results`.
+    async fn save(&self, record: &CheckRecord) -> Result<(), RepositoryError>;
+
+    /// Counts records of given services.
+    async fn count_by_urls(&self, service_urls: &[Url]) -> Result<usize, RepositoryError>;
+
+    /// Fetches records of given services, the latest first.
+    async fn fetch_by_urls(
+        &self,
+        service_urls: &[Url],
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<CheckRecord>, RepositoryError>;
+
+    /// Aggregates records of given services checked since given time.
+    /// Services without such records are omitted.
+    async fn summarize_by_urls(
+        &self,
+        service_urls: &[Url],
+        since: DateTime<Utc>,
+    ) -> Result<Vec<CheckSummary>, RepositoryError>;
+}
+
 /// It can fetch avatar URL with cache.
 #[async_trait]
 pub trait AvatarRepository
@@ -69,3 +285,43 @@ where
         age: Duration,
     ) -> Result<(), RepositoryError>;
 }
+
+/// It can cache the latest check results.
+#[async_trait]
+pub trait CheckResultRepository
+where
+    Self: Sized + Clone + Send + Sync,
+{
+    /// Gets the cached result of the service.
+    async fn get(&self, service_url: &Url) -> Result<Option<CachedCheckResult>, RepositoryError>;
+
+    /// Caches the result of the service with expiration age.
+    async fn save_cache(
+        &self,
+        service_url: &Url,
+        result: &CachedCheckResult,
+        age: Duration,
+    ) -> Result<(), RepositoryError>;
+}
+
+impl Display for RepositoryError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            RepositoryError::NotFound(what) => write!(f, "Not found: {}", what),
+            RepositoryError::Unavailable(e) => write!(f, "Backend unavailable: {}", e),
+            RepositoryError::InvalidData(e) => write!(f, "Invalid data: {}", e),
+            RepositoryError::Upstream(e) => write!(f, "Backend error: {}", e),
+        }
+    }
+}
+
+impl Error for RepositoryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RepositoryError::NotFound(_) => None,
+            RepositoryError::Unavailable(e)
+            | RepositoryError::InvalidData(e)
+            | RepositoryError::Upstream(e) => Some(&**e),
+        }
+    }
+}
@@ -1,11 +1,87 @@
-use std::{error::Error, time::Duration};
-use crate::domain::HttpResponse;
+use crate::domain::{
+    HomoServiceError, HomoServiceErrorKind, HttpResponse, IpVersion, RedirectError,
+};
+use std::{
+    collections::HashMap,
+    error::Error,
+    fmt::{Display, Formatter, Result as FmtResult},
+    future::Future,
+    hash::Hash,
+    mem::forget,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
 use async_trait::async_trait;
+use tokio::sync::{
+    oneshot::{channel as oneshot_channel, Sender as OneshotSender},
+    Semaphore, SemaphorePermit,
+};
 use url::Url;
 
-/// Various error types in service operations.
-pub type ServiceError = Box<dyn Error + Send + Sync>;
+/// Represents errors in service operations.
+#[derive(Debug)]
+pub enum ServiceError {
+    /// The remote server reported that the requested resource does not exist.
+    NotFound(String),
+
+    /// The remote server could not be reached.
+    Unavailable(Box<dyn Error + Send + Sync>),
+
+    /// The response could not be converted.
+    InvalidData(Box<dyn Error + Send + Sync>),
+
+    /// The remote server returned an error or an unexpected response.
+    Upstream(Box<dyn Error + Send + Sync>),
+}
+
+/// Limits the number of in-flight requests globally and per destination address.
+#[derive(Debug)]
+pub struct RequestLimiter {
+    global: Semaphore,
+    per_destination: usize,
+    destinations: Mutex<HashMap<IpAddr, Arc<Semaphore>>>,
+}
+
+/// Represents a permission to send a request. Released on drop.
+#[derive(Debug)]
+pub struct RequestPermit<'a> {
+    // 宣言順に drop されるので、permit を返してから宛先の登録を消す
+    _global: SemaphorePermit<'a>,
+    _destination: OwnedPermit,
+    _entry: DestinationEntry<'a>,
+}
+
+/// Holds a permit of the shared semaphore. Released on drop.
+#[derive(Debug)]
+struct OwnedPermit(Arc<Semaphore>);
+
+/// Removes the semaphore of the destination on drop unless another request uses it.
+#[derive(Debug)]
+struct DestinationEntry<'a> {
+    limiter: &'a RequestLimiter,
+    address: IpAddr,
+    semaphore: Arc<Semaphore>,
+}
+
+/// Coalesces concurrent calls with the same key into one.
+/// Callers arriving while a call is in flight share its result.
+#[derive(Debug)]
+pub struct SingleFlight<K, V> {
+    calls: Mutex<HashMap<K, Vec<OneshotSender<V>>>>,
+    started: AtomicUsize,
+    coalesced: AtomicUsize,
+}
+
+/// Removes the call on drop, e.g. when the leading caller is cancelled.
+struct FlightGuard<'a, K: Hash + Eq, V> {
+    flight: &'a SingleFlight<K, V>,
+    key: &'a K,
+}
 
 /// Represents the container which includes services.
 pub trait Services
@@ -46,4 +122,229 @@ where
     Self: Sized + Send + Sync + Clone,
 {
     async fn request(&self, service_url: &Url) -> Result<(HttpResponse, Duration), ServiceError>;
+
+    /// Requests only over given IP version.
+    async fn request_over(
+        &self,
+        service_url: &Url,
+        version: IpVersion,
+    ) -> Result<(HttpResponse, Duration), ServiceError>;
+}
+
+impl RequestLimiter {
+    /// Constructs with the maximum numbers of in-flight requests.
+    /// Both must be positive.
+    pub fn new(global: usize, per_destination: usize) -> RequestLimiter {
+        RequestLimiter {
+            global: Semaphore::new(global),
+            per_destination,
+            destinations: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until a request to the address can be sent.
+    pub async fn acquire(&self, address: IpAddr) -> RequestPermit<'_> {
+        // 共有ホスティングでは別名のホストも同じサーバーに向くため、名前ではなくアドレスで数える
+        // 途中でキャンセルされても、取得済みのものは drop で返却・削除される
+        let entry = {
+            let mut destinations = self.destinations.lock().unwrap();
+            let per_destination = self.per_destination;
+            let semaphore = destinations
+                .entry(address)
+                .or_insert_with(|| Arc::new(Semaphore::new(per_destination)))
+                .clone();
+            DestinationEntry {
+                limiter: self,
+                address,
+                semaphore,
+            }
+        };
+        let destination = OwnedPermit::acquire(entry.semaphore.clone()).await;
+        let global = self.global.acquire().await;
+
+        RequestPermit {
+            _global: global,
+            _destination: destination,
+            _entry: entry,
+        }
+    }
+
+    /// Returns the number of requests which can be sent immediately, ignoring per-destination limits.
+    pub fn available(&self) -> usize {
+        self.global.available_permits()
+    }
+
+    /// Returns the number of destination addresses with in-flight or waiting requests.
+    pub fn destinations(&self) -> usize {
+        self.destinations.lock().unwrap().len()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> SingleFlight<K, V> {
+    /// Constructs with no calls.
+    pub fn new() -> SingleFlight<K, V> {
+        SingleFlight {
+            calls: Mutex::new(HashMap::new()),
+            started: AtomicUsize::new(0),
+            coalesced: AtomicUsize::new(0),
+        }
+    }
+
+    /// Calls `f` unless a call with the same key is in flight, and returns its result.
+    /// The second element is `true` if the result was shared by another call.
+    pub async fn run<F, Fut>(&self, key: K, f: F) -> (V, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        loop {
+            let receiver = {
+                let mut calls = self.calls.lock().unwrap();
+                match calls.get_mut(&key) {
+                    Some(waiters) => {
+                        let (sender, receiver) = oneshot_channel();
+                        waiters.push(sender);
+                        receiver
+                    }
+                    None => {
+                        calls.insert(key.clone(), vec![]);
+                        break;
+                    }
+                }
+            };
+            // 先行する呼び出しがキャンセルされたら自分で呼び出す
+            if let Ok(value) = receiver.await {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+                return (value, true);
+            }
+        }
+
+        self.started.fetch_add(1, Ordering::Relaxed);
+        let guard = FlightGuard {
+            flight: self,
+            key: &key,
+        };
+        let value = f().await;
+        let waiters = {
+            let mut calls = self.calls.lock().unwrap();
+            // 後から始まった同じキーの呼び出しを消さないように、guard は解除する
+            forget(guard);
+            calls.remove(&key).unwrap_or_default()
+        };
+        for waiter in waiters {
+            waiter.send(value.clone()).ok();
+        }
+
+        (value, false)
+    }
+
+    /// Returns the number of calls actually made.
+    pub fn started(&self) -> usize {
+        self.started.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of calls which shared the result of another.
+    pub fn coalesced(&self) -> usize {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of keys in flight.
+    pub fn in_flight(&self) -> usize {
+        self.calls.lock().unwrap().len()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Default for SingleFlight<K, V> {
+    fn default() -> SingleFlight<K, V> {
+        SingleFlight::new()
+    }
+}
+
+impl<K: Hash + Eq, V> Drop for FlightGuard<'_, K, V> {
+    fn drop(&mut self) {
+        self.flight.calls.lock().unwrap().remove(self.key);
+    }
+}
+
+impl OwnedPermit {
+    /// Waits for a permit of the semaphore.
+    async fn acquire(semaphore: Arc<Semaphore>) -> OwnedPermit {
+        // tokio 0.2.13 には acquire_owned がないので、借用した permit を所有する形に移す
+        semaphore.acquire().await.forget();
+        OwnedPermit(semaphore)
+    }
+}
+
+impl Drop for OwnedPermit {
+    fn drop(&mut self) {
+        self.0.add_permits(1);
+    }
+}
+
+impl Drop for DestinationEntry<'_> {
+    fn drop(&mut self) {
+        // 複製はロック中にしか作られないので、マップとこの登録以外に参照がなければ誰も待っていない
+        let mut destinations = self.limiter.destinations.lock().unwrap();
+        if Arc::strong_count(&self.semaphore) == 2 {
+            destinations.remove(&self.address);
+        }
+    }
+}
+
+impl ServiceError {
+    /// Returns the underlying error.
+    pub fn into_cause(self) -> Box<dyn Error + Send + Sync> {
+        match self {
+            ServiceError::NotFound(what) => what.into(),
+            ServiceError::Unavailable(e)
+            | ServiceError::InvalidData(e)
+            | ServiceError::Upstream(e) => e,
+        }
+    }
+}
+
+impl Display for ServiceError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            ServiceError::NotFound(what) => write!(f, "Not found: {}", what),
+            ServiceError::Unavailable(e) => write!(f, "Service unavailable: {}", e),
+            ServiceError::InvalidData(e) => write!(f, "Invalid data: {}", e),
+            ServiceError::Upstream(e) => write!(f, "Upstream error: {}", e),
+        }
+    }
+}
+
+impl Error for ServiceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ServiceError::NotFound(_) => None,
+            ServiceError::Unavailable(e)
+            | ServiceError::InvalidData(e)
+            | ServiceError::Upstream(e) => Some(&**e),
+        }
+    }
+}
+
+impl From<RedirectError> for ServiceError {
+    fn from(error: RedirectError) -> ServiceError {
+        ServiceError::Upstream(Box::new(error))
+    }
+}
+
+impl From<HomoServiceError> for ServiceError {
+    fn from(error: HomoServiceError) -> ServiceError {
+        match error.kind {
+            HomoServiceErrorKind::Dns
+            | HomoServiceErrorKind::ConnectionRefused
+            | HomoServiceErrorKind::Connection
+            | HomoServiceErrorKind::Tls
+            | HomoServiceErrorKind::Timeout => ServiceError::Unavailable(Box::new(error)),
+            HomoServiceErrorKind::BodyDecode | HomoServiceErrorKind::Blocked => {
+                ServiceError::InvalidData(Box::new(error))
+            }
+            HomoServiceErrorKind::HttpClientError
+            | HomoServiceErrorKind::HttpServerError
+            | HomoServiceErrorKind::Unknown => ServiceError::Upstream(Box::new(error)),
+        }
+    }
 }
@@ -0,0 +1,159 @@
+use super::{HtmlDocument, HtmlParser};
+use crate::domain::{meta_charset, HttpResponse, CHARSET_PRESCAN_SIZE};
+use std::{fmt, mem::take};
+
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
+
+/// Represents the body of a response decoded and tokenized once for all validators.
+/// The body can be given in chunks while it is being read.
+pub struct ResponseContent {
+    is_text: bool,
+    is_html: bool,
+    header_charset: Option<&'static Encoding>,
+    decoder: Option<Decoder>,
+
+    /// The head of the body kept until the encoding is determined.
+    undecoded: Vec<u8>,
+
+    /// The number of bytes in `undecoded` already tokenized as ASCII.
+    tentative: usize,
+
+    parser: HtmlParser,
+}
+
+impl ResponseContent {
+    /// Constructs for the response whose body is about to be read.
+    /// The body already in the response is ignored.
+    pub fn new(response: &HttpResponse) -> ResponseContent {
+        ResponseContent {
+            is_text: response.is_text(),
+            is_html: response.is_html(),
+            header_charset: response.header_charset(),
+            decoder: None,
+            undecoded: vec![],
+            tentative: 0,
+            parser: HtmlParser::new(),
+        }
+    }
+
+    /// Decodes and tokenizes the whole body of the response.
+    pub fn of(response: &HttpResponse) -> ResponseContent {
+        let mut content = ResponseContent::new(response);
+        content.feed(&response.body);
+        content.finish();
+        content
+    }
+
+    /// Appends a chunk of the body.
+    /// If the encoding of HTML is not specified in `Content-Type`, decoding waits until `<meta>`
+    /// charset is found or the head of the body in which it is looked for is read.
+    /// Meanwhile ASCII bytes are tokenized, since they are read as the same in any such encoding.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        if !self.is_text || self.parser.is_ended() {
+            return;
+        }
+        if self.decoder.is_some() {
+            self.decode(chunk, false);
+            return;
+        }
+
+        self.undecoded.extend_from_slice(chunk);
+        if self.header_charset.is_some()
+            || !self.is_html
+            || self.undecoded.len() >= CHARSET_PRESCAN_SIZE
+            || meta_charset(&self.undecoded).is_some()
+        {
+            self.start_decoding();
+            return;
+        }
+
+        // ESC は ISO-2022-JP のエスケープシーケンスを始めるので ASCII として読まない
+        let rest = &self.undecoded[self.tentative..];
+        let ascii = rest
+            .iter()
+            .position(|&b| !b.is_ascii() || b == 0x1b)
+            .unwrap_or(rest.len());
+        if let Ok(text) = std::str::from_utf8(&rest[..ascii]) {
+            self.parser.feed(text);
+            self.tentative += ascii;
+        }
+    }
+
+    /// Marks the end of the body. Validators can decide on the content after this.
+    pub fn finish(&mut self) {
+        if !self.is_text || self.parser.is_ended() {
+            return;
+        }
+        if self.decoder.is_none() {
+            self.start_decoding();
+        }
+        self.decode(&[], true);
+        self.parser.end();
+    }
+
+    /// Checks whether the whole body has been given.
+    pub fn is_finished(&self) -> bool {
+        !self.is_text || self.parser.is_ended()
+    }
+
+    /// Returns the body decoded so far. `None` if the body is not a text.
+    pub fn text(&self) -> Option<&str> {
+        if self.is_text {
+            Some(self.parser.source())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the items tokenized so far. Empty if the body is not a text.
+    pub fn document(&self) -> &HtmlDocument {
+        self.parser.document()
+    }
+
+    /// Determines the encoding in the same way as `HttpResponse::text` and decodes the kept head.
+    fn start_decoding(&mut self) {
+        let encoding = match self.header_charset {
+            Some(encoding) => encoding,
+            None if self.is_html => meta_charset(&self.undecoded).unwrap_or(UTF_8),
+            None => UTF_8,
+        };
+        // BOM は decoder の中で優先される (BOM は ASCII ではないので、あれば先頭から decoder に渡る)
+        self.decoder = Some(encoding.new_decoder());
+        let undecoded = take(&mut self.undecoded);
+        self.decode(&undecoded[self.tentative..], false);
+    }
+
+    /// Decodes the bytes and feeds them to the tokenizer.
+    /// Incomplete characters at the end are kept in the decoder until the next chunk.
+    fn decode(&mut self, mut bytes: &[u8], last: bool) {
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => return,
+        };
+        let mut decoded = String::new();
+        loop {
+            // 出力が足りず途中で止まると、チャンクを跨いだ文字の先頭バイトが失われることがあるので
+            // 最悪の長さを先に確保する
+            decoded.reserve(
+                decoder
+                    .max_utf8_buffer_length(bytes.len())
+                    .unwrap_or(bytes.len()),
+            );
+            let (result, read, _) = decoder.decode_to_string(bytes, &mut decoded, last);
+            bytes = &bytes[read..];
+            if let CoderResult::InputEmpty = result {
+                break;
+            }
+        }
+        self.parser.feed(&decoded);
+    }
+}
+
+impl fmt::Debug for ResponseContent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseContent")
+            .field("text", &self.text())
+            .field("finished", &self.is_finished())
+            .finish()
+    }
+}
@@ -0,0 +1,423 @@
+use std::{fmt, mem::take};
+
+use html5ever::{
+    tendril::StrTendril,
+    tokenizer::{
+        states::RawKind, BufferQueue, Tag, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer,
+        TokenizerOpts,
+    },
+};
+
+/// Represents a start tag in HTML document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlTag {
+    /// The tag name in lowercase.
+    pub name: String,
+
+    /// The attributes. Names are lowercase, and duplicated ones are dropped.
+    pub attributes: Vec<(String, String)>,
+
+    /// The line number where this tag appears.
+    pub line: u64,
+
+    /// The byte offset of `<` in the source.
+    pub offset: usize,
+}
+
+/// Represents the text content of `<script>` element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlScript {
+    /// The text content.
+    pub text: String,
+
+    /// The byte offset of the content in the source.
+    pub offset: usize,
+}
+
+/// Represents the tokenized content of HTML document.
+/// The tokenization follows WHATWG HTML rules as browsers do.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HtmlDocument {
+    /// Start tags in document order.
+    pub tags: Vec<HtmlTag>,
+
+    /// The text contents of `<script>` elements.
+    pub scripts: Vec<HtmlScript>,
+
+    /// The contents of comments.
+    pub comments: Vec<String>,
+
+    /// The text contents except for those of raw text elements.
+    pub texts: Vec<String>,
+}
+
+impl HtmlTag {
+    /// Returns the value of the attribute.
+    /// The name must be lowercase.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| &v[..])
+    }
+
+    /// Returns the byte offset of the attribute value in the source.
+    /// The name must be lowercase. For attributes without value, returns the offset of the name.
+    pub fn attribute_offset(&self, source: &str, name: &str) -> Option<usize> {
+        let bytes = source.as_bytes();
+        let is_space = |b: u8| b.is_ascii_whitespace();
+        let mut i = self.offset + 1;
+        while i < bytes.len() && !is_space(bytes[i]) && bytes[i] != b'/' && bytes[i] != b'>' {
+            i += 1;
+        }
+
+        // 属性は html5ever と同じ規則で読み、重複したものは最初を採る
+        loop {
+            while i < bytes.len() && (is_space(bytes[i]) || bytes[i] == b'/') {
+                i += 1;
+            }
+            if i >= bytes.len() || bytes[i] == b'>' {
+                return None;
+            }
+
+            let name_start = i;
+            i += 1;
+            while i < bytes.len() && !is_space(bytes[i]) && !matches!(bytes[i], b'/' | b'>' | b'=')
+            {
+                i += 1;
+            }
+            let found = source[name_start..i].eq_ignore_ascii_case(name);
+            while i < bytes.len() && is_space(bytes[i]) {
+                i += 1;
+            }
+            if i >= bytes.len() || bytes[i] != b'=' {
+                if found {
+                    return Some(name_start);
+                }
+                continue;
+            }
+
+            i += 1;
+            while i < bytes.len() && is_space(bytes[i]) {
+                i += 1;
+            }
+            let value_start = match bytes.get(i) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    i += 1;
+                    let value_start = i;
+                    while i < bytes.len() && bytes[i] != quote {
+                        i += 1;
+                    }
+                    i += 1;
+                    value_start
+                }
+                _ => {
+                    let value_start = i;
+                    while i < bytes.len() && !is_space(bytes[i]) && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value_start
+                }
+            };
+            if found {
+                return Some(value_start);
+            }
+        }
+    }
+}
+
+impl HtmlScript {
+    /// Converts the byte index in `text` to the byte offset in the source.
+    /// Considers newline normalization and replacement of NUL by the tokenizer.
+    pub fn source_offset(&self, source: &str, index: usize) -> usize {
+        let mut chars = source[self.offset..].char_indices().peekable();
+        let mut consumed = 0;
+        while let Some((raw, c)) = chars.next() {
+            if consumed >= index {
+                return self.offset + raw;
+            }
+            consumed += match c {
+                '\r' if chars.peek().map(|&(_, c)| c) == Some('\n') => 0,
+                '\0' => '\u{fffd}'.len_utf8(),
+                c => c.len_utf8(),
+            };
+        }
+        source.len()
+    }
+}
+
+impl HtmlDocument {
+    /// Tokenizes HTML.
+    pub fn parse(html: &str) -> HtmlDocument {
+        let mut parser = HtmlParser::new();
+        parser.feed(html);
+        parser.end();
+        parser.document
+    }
+
+    /// Returns start tags with given lowercase name.
+    pub fn tags_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a HtmlTag> + 'a {
+        self.tags.iter().filter(move |t| t.name == name)
+    }
+
+    /// Moves the items of another document to the end.
+    fn append(&mut self, other: &mut HtmlDocument) {
+        self.tags.append(&mut other.tags);
+        self.scripts.append(&mut other.scripts);
+        self.comments.append(&mut other.comments);
+        self.texts.append(&mut other.texts);
+    }
+}
+
+/// Tokenizes HTML given in chunks, such as a response body being read.
+/// The tokenizer keeps its state between chunks, so each chunk is processed only once.
+/// Items appear in the document once they are closed, e.g. a tag by `>` and a script by its end tag.
+#[derive(Debug)]
+pub struct HtmlParser {
+    source: String,
+    document: HtmlDocument,
+    tokenizer: Option<TokenizerCell>,
+}
+
+impl HtmlParser {
+    /// Constructs with no input.
+    pub fn new() -> HtmlParser {
+        HtmlParser {
+            source: String::new(),
+            document: HtmlDocument::default(),
+            tokenizer: Some(TokenizerCell::new()),
+        }
+    }
+
+    /// Appends the text and tokenizes as far as possible.
+    /// Ignored after `HtmlParser::end` is called.
+    pub fn feed(&mut self, text: &str) {
+        let tokenizer = match &mut self.tokenizer {
+            Some(tokenizer) => tokenizer,
+            None => return,
+        };
+        self.source.push_str(text);
+        tokenizer.feed(text);
+        self.document.append(tokenizer.emitted());
+    }
+
+    /// Tokenizes the rest of the input as the end of the document.
+    pub fn end(&mut self) {
+        if let Some(mut tokenizer) = self.tokenizer.take() {
+            tokenizer.end();
+            self.document.append(tokenizer.emitted());
+        }
+    }
+
+    /// Returns the text fed so far.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Returns the items tokenized so far.
+    pub fn document(&self) -> &HtmlDocument {
+        &self.document
+    }
+
+    /// Checks whether `HtmlParser::end` has been called.
+    pub fn is_ended(&self) -> bool {
+        self.tokenizer.is_none()
+    }
+}
+
+impl Default for HtmlParser {
+    fn default() -> HtmlParser {
+        HtmlParser::new()
+    }
+}
+
+/// Holds the tokenizer and its input queue between chunks.
+struct TokenizerCell {
+    tokenizer: Tokenizer<DocumentSink>,
+    queue: BufferQueue,
+}
+
+// tendril は参照カウントが非アトミックなので Send でも Sync でもない
+// tendril はすべてこの構造体の中で作られて保持され、sink は中身を String に写して手放すので、
+// 構造体ごとスレッドを移るのは安全 (Send)
+// また &self を取るメソッドを持たないので、参照を共有しても中身には触れられない (Sync)
+unsafe impl Send for TokenizerCell {}
+unsafe impl Sync for TokenizerCell {}
+
+impl TokenizerCell {
+    fn new() -> TokenizerCell {
+        TokenizerCell {
+            tokenizer: Tokenizer::new(DocumentSink::default(), TokenizerOpts::default()),
+            queue: BufferQueue::new(),
+        }
+    }
+
+    /// Feeds the text.
+    fn feed(&mut self, text: &str) {
+        // タグは `>` で閉じた時点で出てくるので、`>` ごとに区切って流し込めば位置がわかる
+        for chunk in text.split_inclusive('>') {
+            let sink = &mut self.tokenizer.sink;
+            sink.position += chunk.len();
+            sink.span.push_str(chunk);
+            self.queue.push_back(StrTendril::from_slice(chunk));
+            let _ = self.tokenizer.feed(&mut self.queue);
+        }
+    }
+
+    /// Finishes the input.
+    fn end(&mut self) {
+        self.tokenizer.end();
+    }
+
+    /// Returns the items emitted since they were last taken.
+    fn emitted(&mut self) -> &mut HtmlDocument {
+        &mut self.tokenizer.sink.document
+    }
+}
+
+impl fmt::Debug for TokenizerCell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TokenizerCell").finish()
+    }
+}
+
+/// Collects tokens into `HtmlDocument`.
+#[derive(Debug, Default)]
+struct DocumentSink {
+    /// The items emitted but not yet taken by `HtmlParser`.
+    document: HtmlDocument,
+    raw_element: Option<String>,
+    characters: String,
+
+    /// The source fed after the last token except characters.
+    span: String,
+
+    /// The byte offset in the source which has been fed.
+    position: usize,
+
+    /// The byte offset where the last token except characters ended.
+    boundary: usize,
+
+    /// The byte offset where the content of the raw text element starts.
+    raw_offset: usize,
+}
+
+impl DocumentSink {
+    /// Finds `<` which starts the tag emitted now.
+    /// Between the last token and the tag, only characters in data state appear,
+    /// so the first `<` followed by an ASCII letter starts the tag.
+    fn tag_offset(&self) -> usize {
+        self.span
+            .as_bytes()
+            .windows(2)
+            .position(|w| w[0] == b'<' && w[1].is_ascii_alphabetic())
+            .map(|i| self.boundary + i)
+            .unwrap_or(self.boundary)
+    }
+    /// Moves the buffered characters to the appropriate place.
+    fn flush_characters(&mut self) {
+        if self.characters.is_empty() {
+            return;
+        }
+        let characters = take(&mut self.characters);
+        match self.raw_element.as_deref() {
+            Some("script") => self.document.scripts.push(HtmlScript {
+                text: characters,
+                offset: self.raw_offset,
+            }),
+            Some(_) => (),
+            None => self.document.texts.push(characters),
+        }
+    }
+
+    /// Processes a start tag and returns the next tokenizer state.
+    fn start_tag(&mut self, tag: Tag, line: u64) -> TokenSinkResult<()> {
+        let name = tag.name.to_string();
+        let mut attributes: Vec<(String, String)> = vec![];
+        for attr in tag.attrs {
+            let attr_name = attr.name.local.to_string();
+            if attributes.iter().all(|(n, _)| n != &attr_name) {
+                attributes.push((attr_name, attr.value.to_string()));
+            }
+        }
+        self.document.tags.push(HtmlTag {
+            name: name.clone(),
+            attributes,
+            line,
+            offset: self.tag_offset(),
+        });
+        self.raw_offset = self.position;
+
+        // ツリー構築段階で行われる状態遷移をここで再現する
+        let raw_kind = match &name[..] {
+            _ if tag.self_closing => None,
+            "script" => Some(RawKind::ScriptData),
+            "title" | "textarea" => Some(RawKind::Rcdata),
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" => {
+                Some(RawKind::Rawtext)
+            }
+            "plaintext" => {
+                self.raw_element = Some(name);
+                return TokenSinkResult::Plaintext;
+            }
+            _ => None,
+        };
+        match raw_kind {
+            Some(kind) => {
+                self.raw_element = Some(name);
+                TokenSinkResult::RawData(kind)
+            }
+            None => TokenSinkResult::Continue,
+        }
+    }
+}
+
+impl TokenSink for DocumentSink {
+    type Handle = ();
+
+    fn process_token(&mut self, token: Token, line: u64) -> TokenSinkResult<()> {
+        // 文字やエラーはチャンクの途中でも出てくるので境界にしない
+        let closes = matches!(
+            token,
+            Token::TagToken(_) | Token::CommentToken(_) | Token::DoctypeToken(_)
+        );
+        let result = self.process(token, line);
+        if closes {
+            self.boundary = self.position;
+            self.span.clear();
+        }
+        result
+    }
+}
+
+impl DocumentSink {
+    /// Processes a token.
+    fn process(&mut self, token: Token, line: u64) -> TokenSinkResult<()> {
+        match token {
+            Token::CharacterTokens(chars) => {
+                self.characters.push_str(&chars);
+                TokenSinkResult::Continue
+            }
+            Token::TagToken(tag) => {
+                self.flush_characters();
+                match tag.kind {
+                    TagKind::StartTag => self.start_tag(tag, line),
+                    TagKind::EndTag => {
+                        self.raw_element = None;
+                        TokenSinkResult::Continue
+                    }
+                }
+            }
+            Token::CommentToken(comment) => {
+                self.flush_characters();
+                self.document.comments.push(comment.to_string());
+                TokenSinkResult::Continue
+            }
+            Token::EOFToken => {
+                self.flush_characters();
+                TokenSinkResult::Continue
+            }
+            _ => TokenSinkResult::Continue,
+        }
+    }
+}
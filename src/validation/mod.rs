@@ -1 +1,16 @@
+//! Contains validators and the target URLs they look for.
+
+mod content;
+mod document;
+mod refresh;
 pub mod response;
+mod site;
+mod target;
+
+pub use self::{
+    content::ResponseContent,
+    document::{HtmlDocument, HtmlParser, HtmlScript, HtmlTag},
+    refresh::Refresh,
+    site::{is_downgrade, is_same_site, registrable_domain},
+    target::TargetUrls,
+};
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+/// Represents the parsed value of `Refresh` header or `<meta http-equiv="refresh">`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refresh<'a> {
+    /// The delay before refreshing. Fractional part is ignored as browsers do.
+    pub delay: Duration,
+
+    /// The URL to navigate. `None` means reloading the document itself.
+    pub url: Option<&'a str>,
+}
+
+impl<'a> Refresh<'a> {
+    /// Parses the value by the shared declarative refresh steps in WHATWG HTML.
+    /// Returns `None` if the value is not valid.
+    pub fn parse(value: &'a str) -> Option<Refresh<'a>> {
+        let rest = skip_whitespace(value);
+
+        // 遅延秒数 (小数部は読み飛ばす)
+        let digits_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (digits, rest) = rest.split_at(digits_end);
+        if digits.is_empty() && !rest.starts_with('.') {
+            return None;
+        }
+        let delay = match digits {
+            "" => Duration::from_secs(0),
+            digits => Duration::from_secs(digits.parse().unwrap_or(u64::MAX)),
+        };
+        let rest = rest.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+        // 区切り
+        if rest.is_empty() {
+            return Some(Refresh { delay, url: None });
+        }
+        if !rest.starts_with(|c: char| c == ';' || c == ',' || c.is_ascii_whitespace()) {
+            return None;
+        }
+        let rest = skip_whitespace(rest);
+        let rest = skip_whitespace(rest.strip_prefix(&[';', ','][..]).unwrap_or(rest));
+        if rest.is_empty() {
+            return Some(Refresh { delay, url: None });
+        }
+
+        // url= は省略できる
+        let url = match strip_url_prefix(rest) {
+            Some(after) => skip_quotes(after),
+            None if rest.starts_with(&['u', 'U'][..]) => rest,
+            None => skip_quotes(rest),
+        };
+        let url = url.trim_matches(|c: char| c.is_ascii_whitespace());
+
+        Some(Refresh {
+            delay,
+            url: if url.is_empty() { None } else { Some(url) },
+        })
+    }
+}
+
+/// Skips leading ASCII whitespace.
+fn skip_whitespace(value: &str) -> &str {
+    value.trim_start_matches(|c: char| c.is_ascii_whitespace())
+}
+
+/// Strips `url =` prefix case-insensitively.
+fn strip_url_prefix(value: &str) -> Option<&str> {
+    let prefix = value.get(..3)?;
+    if !prefix.eq_ignore_ascii_case("url") {
+        return None;
+    }
+    skip_whitespace(&value[3..])
+        .strip_prefix('=')
+        .map(skip_whitespace)
+}
+
+/// Strips the leading quote and everything after the matching one.
+fn skip_quotes(value: &str) -> &str {
+    match value.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            let quoted = &value[1..];
+            match quoted.find(quote) {
+                Some(end) => &quoted[..end],
+                None => quoted,
+            }
+        }
+        _ => value,
+    }
+}
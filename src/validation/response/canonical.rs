@@ -0,0 +1,90 @@
+use super::{ScanState, ValidateResponse, Verdict};
+use crate::{
+    domain::{HomoServiceStatus, HttpResponse},
+    validation::{HtmlTag, ResponseContent, TargetUrls},
+};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+/// Validates the response based on `<link rel="canonical">` and `og:url` declarations.
+pub struct ResponseCanonicalValidator {
+    targets: Arc<TargetUrls>,
+}
+
+impl ResponseCanonicalValidator {
+    pub fn new(targets: Arc<TargetUrls>) -> ResponseCanonicalValidator {
+        ResponseCanonicalValidator { targets }
+    }
+
+    /// Returns the attribute name and the declared URL
+    /// if the tag is a canonical or `og:url` declaration.
+    fn declared_url(tag: &HtmlTag) -> Option<(&'static str, &str)> {
+        match &tag.name[..] {
+            "link" => {
+                let rel = tag.attribute("rel")?;
+                if rel
+                    .split_ascii_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case("canonical"))
+                {
+                    tag.attribute("href").map(|href| ("href", href))
+                } else {
+                    None
+                }
+            }
+            "meta" => {
+                let property = tag
+                    .attribute("property")
+                    .or_else(|| tag.attribute("name"))?;
+                if property.trim().eq_ignore_ascii_case("og:url") {
+                    tag.attribute("content").map(|content| ("content", content))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl ValidateResponse for ResponseCanonicalValidator {
+    fn name(&self) -> &str {
+        "canonical"
+    }
+
+    async fn validate(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+    ) -> Option<Verdict> {
+        self.scan(response, content, &mut ScanState::default())
+            .await
+    }
+
+    async fn scan(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+        state: &mut ScanState,
+    ) -> Option<Verdict> {
+        let body = content.text()?;
+        let tags = &content.document().tags[state.tags..];
+        state.tags += tags.len();
+        tags.iter()
+            .filter_map(|tag| {
+                ResponseCanonicalValidator::declared_url(tag).map(|declared| (tag, declared))
+            })
+            .find(|(_, (_, url))| {
+                response
+                    .url
+                    .join(url.trim())
+                    .map(|u| self.targets.matches(&u))
+                    .unwrap_or(false)
+            })
+            .map(|(tag, (attribute, url))| {
+                let offset = tag.attribute_offset(body, attribute);
+                Verdict::new(HomoServiceStatus::CanonicalContent).matched_at(url, offset)
+            })
+    }
+}
@@ -1,15 +1,52 @@
-use super::ValidateResponse;
-use crate::domain::{HomoServiceStatus, HttpResponse};
+use super::{ValidateResponse, Verdict};
+use crate::{
+    domain::{HomoServiceStatus, HttpResponse},
+    validation::{Refresh, ResponseContent, TargetUrls},
+};
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use http::StatusCode;
 
-/// Validates the response based on its response status and `Location` header.
-pub enum ResponseHeaderValidator {}
+/// Validates the response based on its response status, `Location` and `Refresh` header.
+/// Relative URLs are resolved against the URL of the response.
+pub struct ResponseHeaderValidator {
+    targets: Arc<TargetUrls>,
+}
+
+impl ResponseHeaderValidator {
+    pub fn new(targets: Arc<TargetUrls>) -> ResponseHeaderValidator {
+        ResponseHeaderValidator { targets }
+    }
+
+    /// Validates `Refresh` header in the same way as `<meta http-equiv="refresh">`.
+    /// Returns `None` for other URLs so that the body can be validated.
+    fn validate_refresh(&self, response: &HttpResponse) -> Option<Verdict> {
+        let value = response.headers.get("refresh")?;
+        let url = response.url.join(Refresh::parse(value)?.url?).ok()?;
+        if self.targets.matches(&url) {
+            Some(Verdict::new(HomoServiceStatus::RedirectContent).matched(value))
+        } else {
+            None
+        }
+    }
+}
 
 #[async_trait]
 impl ValidateResponse for ResponseHeaderValidator {
-    async fn validate(response: &HttpResponse) -> Option<HomoServiceStatus> {
+    fn name(&self) -> &str {
+        "header"
+    }
+
+    fn reads_body(&self) -> bool {
+        false
+    }
+
+    async fn validate(
+        &self,
+        response: &HttpResponse,
+        _content: &ResponseContent,
+    ) -> Option<Verdict> {
         match response.status {
             StatusCode::MOVED_PERMANENTLY
             | StatusCode::FOUND
@@ -18,15 +55,15 @@ impl ValidateResponse for ResponseHeaderValidator {
             | StatusCode::PERMANENT_REDIRECT => {
                 let location = match response.headers.get("location") {
                     Some(loc) => loc,
-                    None => return Some(HomoServiceStatus::Invalid),
+                    None => return Some(Verdict::new(HomoServiceStatus::Invalid)),
+                };
+                let status = match response.url.join(location.trim()) {
+                    Ok(url) if self.targets.matches(&url) => HomoServiceStatus::RedirectResponse,
+                    _ => HomoServiceStatus::Invalid,
                 };
-                if location.starts_with("https://twitter.com/mpyw") {
-                    Some(HomoServiceStatus::RedirectResponse)
-                } else {
-                    Some(HomoServiceStatus::Invalid)
-                }
+                Some(Verdict::new(status).matched(location))
             }
-            _ => None,
+            _ => self.validate_refresh(response),
         }
     }
 }
@@ -1,45 +1,68 @@
-use super::ValidateResponse;
-use crate::domain::{HomoServiceStatus, HttpResponse};
+use super::{ScanState, ValidateResponse, Verdict};
+use crate::{
+    domain::{HomoServiceStatus, HttpResponse},
+    validation::{Refresh, ResponseContent, TargetUrls},
+};
+use std::sync::Arc;
 
 use async_trait::async_trait;
-use lazy_static::lazy_static;
-use regex::Regex;
 
-lazy_static! {
-    static ref REGEX_HTML_META: Regex = Regex::new(r#"<meta\s+([^>]+)\s*>"#).unwrap();
-    static ref REGEX_HTML_ATTR: Regex = Regex::new(r#"([a-zA-Z0-9\-]+)="([^"]+)""#).unwrap();
-    static ref REGEX_TARGET_URL: Regex = Regex::new(r#"https://twitter\.com/mpyw"#).unwrap();
+/// Validates the response based on redirect meta elements in its HTML response body.
+pub struct ResponseHtmlValidator {
+    targets: Arc<TargetUrls>,
 }
 
-/// Validates the response based on its HTML response body.
-pub enum ResponseHtmlValidator {}
+impl ResponseHtmlValidator {
+    pub fn new(targets: Arc<TargetUrls>) -> ResponseHtmlValidator {
+        ResponseHtmlValidator { targets }
+    }
+}
 
 #[async_trait]
 impl ValidateResponse for ResponseHtmlValidator {
-    async fn validate(response: &HttpResponse) -> Option<HomoServiceStatus> {
-        for meta in REGEX_HTML_META.captures_iter(&response.body) {
-            let mut http_equiv = false;
-            let mut content = false;
-            for attr in REGEX_HTML_ATTR.captures_iter(&meta[1]) {
-                match &attr[1] {
-                    "http-equiv" => {
-                        http_equiv |= &attr[2].to_lowercase() == "refresh";
-                    }
-                    "content" => {
-                        content |= REGEX_TARGET_URL.is_match(&attr[2]);
-                    }
-                    _ => continue,
-                }
-            }
-            if http_equiv && content {
-                return Some(HomoServiceStatus::RedirectContent);
-            }
-        }
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    async fn validate(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+    ) -> Option<Verdict> {
+        self.scan(response, content, &mut ScanState::default())
+            .await
+    }
 
-        if REGEX_TARGET_URL.is_match(&response.body) {
-            Some(HomoServiceStatus::LinkContent)
-        } else {
-            None
+    async fn scan(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+        state: &mut ScanState,
+    ) -> Option<Verdict> {
+        let body = content.text()?;
+        let tags = &content.document().tags[state.tags..];
+        state.tags += tags.len();
+        for meta in tags.iter().filter(|t| t.name == "meta") {
+            let http_equiv = meta
+                .attribute("http-equiv")
+                .map(|v| v.trim().eq_ignore_ascii_case("refresh"))
+                .unwrap_or(false);
+            let content = match meta.attribute("content") {
+                Some(content) => content,
+                None => continue,
+            };
+            let redirects = Refresh::parse(content)
+                .and_then(|r| r.url)
+                .and_then(|u| response.url.join(u).ok())
+                .map(|u| self.targets.matches(&u))
+                .unwrap_or(false);
+            if http_equiv && redirects {
+                let offset = meta.attribute_offset(body, "content");
+                return Some(
+                    Verdict::new(HomoServiceStatus::RedirectContent).matched_at(content, offset),
+                );
+            }
         }
+        None
     }
 }
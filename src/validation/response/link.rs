@@ -0,0 +1,60 @@
+use super::{ScanState, ValidateResponse, Verdict};
+use crate::{
+    domain::{HomoServiceStatus, HttpResponse},
+    validation::{ResponseContent, TargetUrls},
+};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+/// Validates the response based on `<a href>` and `<area href>` pointing to the target.
+pub struct ResponseLinkValidator {
+    targets: Arc<TargetUrls>,
+}
+
+impl ResponseLinkValidator {
+    pub fn new(targets: Arc<TargetUrls>) -> ResponseLinkValidator {
+        ResponseLinkValidator { targets }
+    }
+}
+
+#[async_trait]
+impl ValidateResponse for ResponseLinkValidator {
+    fn name(&self) -> &str {
+        "link"
+    }
+
+    async fn validate(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+    ) -> Option<Verdict> {
+        self.scan(response, content, &mut ScanState::default())
+            .await
+    }
+
+    async fn scan(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+        state: &mut ScanState,
+    ) -> Option<Verdict> {
+        let body = content.text()?;
+        let tags = &content.document().tags[state.tags..];
+        state.tags += tags.len();
+        tags.iter()
+            .filter(|t| t.name == "a" || t.name == "area")
+            .filter_map(|t| t.attribute("href").map(|href| (t, href)))
+            .find(|(_, href)| {
+                response
+                    .url
+                    .join(href.trim())
+                    .map(|u| self.targets.matches(&u))
+                    .unwrap_or(false)
+            })
+            .map(|(tag, href)| {
+                let offset = tag.attribute_offset(body, "href");
+                Verdict::new(HomoServiceStatus::LinkContent).matched_at(href, offset)
+            })
+    }
+}
@@ -0,0 +1,61 @@
+use super::{ScanState, ValidateResponse, Verdict};
+use crate::{
+    domain::{HomoServiceStatus, HttpResponse},
+    validation::{ResponseContent, TargetUrls},
+};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+/// Validates the response based on target URLs mentioned anywhere in its body,
+/// including comments, scripts and attributes.
+pub struct ResponseMentionValidator {
+    targets: Arc<TargetUrls>,
+}
+
+impl ResponseMentionValidator {
+    pub fn new(targets: Arc<TargetUrls>) -> ResponseMentionValidator {
+        ResponseMentionValidator { targets }
+    }
+}
+
+#[async_trait]
+impl ValidateResponse for ResponseMentionValidator {
+    fn name(&self) -> &str {
+        "mention"
+    }
+
+    async fn validate(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+    ) -> Option<Verdict> {
+        self.scan(response, content, &mut ScanState::default())
+            .await
+    }
+
+    async fn scan(
+        &self,
+        _response: &HttpResponse,
+        content: &ResponseContent,
+        state: &mut ScanState,
+    ) -> Option<Verdict> {
+        let body = content.text()?;
+
+        // 末尾の URL は続きを読むと変わりうるので、URL に含まれない文字の手前までを見る
+        let end = if content.is_finished() {
+            body.len()
+        } else {
+            body[state.text..]
+                .rfind(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | '\\'))
+                .map(|index| state.text + index)
+                .unwrap_or(state.text)
+        };
+        let scanned = &body[state.text..end];
+        let start = state.text;
+        state.text = end;
+
+        let (offset, found) = self.targets.find_in(scanned)?;
+        Some(Verdict::new(HomoServiceStatus::MentionOnly).matched_at(found, Some(start + offset)))
+    }
+}
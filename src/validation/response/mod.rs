@@ -1,20 +1,126 @@
 //! Contains validators related to service responses.
 
+mod canonical;
 mod header;
 mod html;
+mod link;
+mod mention;
+mod pipeline;
+mod script;
 
-pub use self::{header::ResponseHeaderValidator, html::ResponseHtmlValidator};
+pub use self::{
+    canonical::ResponseCanonicalValidator,
+    header::ResponseHeaderValidator,
+    html::ResponseHtmlValidator,
+    link::ResponseLinkValidator,
+    mention::ResponseMentionValidator,
+    pipeline::{PipelineScan, ValidatorPipeline, DEFAULT_VALIDATORS},
+    script::ResponseScriptValidator,
+};
 
-use crate::domain::{HomoServiceStatus, HttpResponse};
+use crate::{
+    domain::{HomoServiceStatus, HttpResponse},
+    validation::ResponseContent,
+};
 
 use async_trait::async_trait;
 
+/// Represents a status determined by a validator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    /// The determined status.
+    pub status: HomoServiceStatus,
+
+    /// The matched part, such as `Location` value or meta element attribute.
+    pub matched: Option<String>,
+
+    /// The byte offset of the matched part in the decoded body.
+    pub offset: Option<usize>,
+}
+
+impl Verdict {
+    /// Constructs without any matched part.
+    pub fn new(status: HomoServiceStatus) -> Verdict {
+        Verdict {
+            status,
+            matched: None,
+            offset: None,
+        }
+    }
+
+    /// Sets the matched part outside of the body.
+    pub fn matched(self, matched: impl Into<String>) -> Verdict {
+        Verdict {
+            matched: Some(matched.into()),
+            ..self
+        }
+    }
+
+    /// Sets the matched part and its byte offset in the body.
+    pub fn matched_at(self, matched: impl Into<String>, offset: Option<usize>) -> Verdict {
+        Verdict {
+            matched: Some(matched.into()),
+            offset,
+            ..self
+        }
+    }
+}
+
+/// Keeps how far a validator has looked into the content of a response being read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanState {
+    /// The number of tags already looked at.
+    pub tags: usize,
+
+    /// The number of scripts already looked at.
+    pub scripts: usize,
+
+    /// The byte offset in the text up to which it has been looked at.
+    pub text: usize,
+
+    /// The verdict which holds unless another one is found in the rest of the body.
+    pub tentative: Option<Verdict>,
+}
+
 /// Indicates that it validates `HttpResponse`.
 #[async_trait]
-pub trait ValidateResponse {
+pub trait ValidateResponse
+where
+    Self: Send + Sync,
+{
+    /// Returns the name of this validator.
+    fn name(&self) -> &str;
+
+    /// Returns whether this validator looks into the response body.
+    /// Validators which don't can decide the status before the body is read.
+    fn reads_body(&self) -> bool {
+        true
+    }
+
     /// Validates the response.
+    /// `content` is the whole body of the response, decoded and tokenized once for all validators.
     /// Returns `None` if any valid URL was found.
-    async fn validate(response: &HttpResponse) -> Option<HomoServiceStatus>;
+    async fn validate(&self, response: &HttpResponse, content: &ResponseContent)
+        -> Option<Verdict>;
+
+    /// Validates the response while its body is being read.
+    /// `state` is kept between calls for the same response, so that each call only looks at
+    /// the content appended since the last one.
+    /// Returns a verdict only when the rest of the body cannot change it.
+    ///
+    /// By default, waits until the whole body is read unless this validator doesn't read it.
+    async fn scan(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+        _state: &mut ScanState,
+    ) -> Option<Verdict> {
+        if content.is_finished() || !self.reads_body() {
+            self.validate(response, content).await
+        } else {
+            None
+        }
+    }
 }
 
 #[async_trait]
@@ -25,12 +131,13 @@ where
 {
     /// Validates the response.
     /// Returns `None` if any valid URL was found.
-    async fn validate<V: ValidateResponse>(&self) -> Option<HomoServiceStatus>;
+    async fn validate<V: ValidateResponse>(&self, validator: &V) -> Option<HomoServiceStatus>;
 }
 
 #[async_trait]
 impl ValidateResponseExt for HttpResponse {
-    async fn validate<V: ValidateResponse>(&self) -> Option<HomoServiceStatus> {
-        V::validate(self).await
+    async fn validate<V: ValidateResponse>(&self, validator: &V) -> Option<HomoServiceStatus> {
+        let content = ResponseContent::of(self);
+        validator.validate(self, &content).await.map(|v| v.status)
     }
 }
@@ -0,0 +1,182 @@
+use super::{
+    ResponseCanonicalValidator, ResponseHeaderValidator, ResponseHtmlValidator,
+    ResponseLinkValidator, ResponseMentionValidator, ResponseScriptValidator, ScanState,
+    ValidateResponse, Verdict,
+};
+use crate::{
+    domain::{HomoServiceEvidence, HomoServiceStatus, HttpResponse},
+    validation::{ResponseContent, TargetUrls},
+};
+use std::{error::Error, fmt, sync::Arc};
+
+/// The names of validators enabled by default, in order.
+pub const DEFAULT_VALIDATORS: &[&str] =
+    &["header", "html", "script", "canonical", "link", "mention"];
+
+/// Represents an ordered list of validators.
+/// The first status returned by them is the result.
+#[derive(Clone, Default)]
+pub struct ValidatorPipeline {
+    validators: Vec<Arc<dyn ValidateResponse>>,
+}
+
+impl ValidatorPipeline {
+    /// Constructs an empty pipeline.
+    pub fn new() -> ValidatorPipeline {
+        ValidatorPipeline::default()
+    }
+
+    /// Constructs from names of built-in validators.
+    /// Available names are `header`, `html`, `script`, `canonical`, `link` and `mention`.
+    pub fn from_names<'a>(
+        names: impl IntoIterator<Item = &'a str>,
+        targets: Arc<TargetUrls>,
+    ) -> Result<ValidatorPipeline, Box<dyn Error + Send + Sync>> {
+        let mut pipeline = ValidatorPipeline::new();
+        for name in names {
+            let targets = targets.clone();
+            match name.trim() {
+                "header" => pipeline.push(ResponseHeaderValidator::new(targets)),
+                "html" => pipeline.push(ResponseHtmlValidator::new(targets)),
+                "script" => pipeline.push(ResponseScriptValidator::new(targets)),
+                "canonical" => pipeline.push(ResponseCanonicalValidator::new(targets)),
+                "link" => pipeline.push(ResponseLinkValidator::new(targets)),
+                "mention" => pipeline.push(ResponseMentionValidator::new(targets)),
+                "" => continue,
+                otherwise => return Err(format!("Unknown validator: {}", otherwise).into()),
+            }
+        }
+
+        Ok(pipeline)
+    }
+
+    /// Appends a validator.
+    pub fn push(&mut self, validator: impl ValidateResponse + 'static) {
+        self.validators.push(Arc::new(validator));
+    }
+
+    /// Appends a validator and returns itself.
+    pub fn with(mut self, validator: impl ValidateResponse + 'static) -> ValidatorPipeline {
+        self.push(validator);
+        self
+    }
+
+    /// Returns the names of validators in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.validators.iter().map(|v| v.name()).collect()
+    }
+
+    /// Validates the response with validators in order.
+    /// Returns `HomoServiceStatus::Invalid` if no validator returned a status.
+    pub async fn validate(&self, response: &HttpResponse) -> HomoServiceStatus {
+        self.judge(response).await.0
+    }
+
+    /// Validates the response and returns the status with its evidence.
+    /// The evidence is `None` if no validator returned a status.
+    pub async fn judge(
+        &self,
+        response: &HttpResponse,
+    ) -> (HomoServiceStatus, Option<HomoServiceEvidence>) {
+        let content = ResponseContent::of(response);
+        self.scan()
+            .judge(response, &content)
+            .await
+            .unwrap_or((HomoServiceStatus::Invalid, None))
+    }
+
+    /// Validates the response whose body has not been read.
+    /// Returns `Some` if the status is decided regardless of the body.
+    pub async fn judge_header(
+        &self,
+        response: &HttpResponse,
+    ) -> Option<(HomoServiceStatus, Option<HomoServiceEvidence>)> {
+        let content = ResponseContent::new(response);
+        self.scan().judge(response, &content).await
+    }
+
+    /// Validates the response whose body has been partially read.
+    /// Returns `Some` only if reading the rest of the body cannot change the result.
+    pub async fn judge_partial(
+        &self,
+        response: &HttpResponse,
+    ) -> Option<(HomoServiceStatus, Option<HomoServiceEvidence>)> {
+        let mut content = ResponseContent::new(response);
+        content.feed(&response.body);
+        self.scan().judge(response, &content).await
+    }
+
+    /// Starts validating a response while its body is being read.
+    pub fn scan(&self) -> PipelineScan<'_> {
+        PipelineScan {
+            validators: &self.validators,
+            states: vec![ScanState::default(); self.validators.len()],
+            verdicts: vec![None; self.validators.len()],
+        }
+    }
+}
+
+/// Validates a response with `ValidatorPipeline` as its body is read.
+/// Each validator keeps its state between calls, so each chunk of the body is looked at once.
+pub struct PipelineScan<'a> {
+    validators: &'a [Arc<dyn ValidateResponse>],
+    states: Vec<ScanState>,
+    verdicts: Vec<Option<Verdict>>,
+}
+
+impl PipelineScan<'_> {
+    /// Validates the content read so far.
+    /// Returns `Some` once reading the rest of the body cannot change the result, that is,
+    /// a validator has returned a verdict and every validator before it has given up.
+    /// Validators which read the body give up only after the whole body is read,
+    /// since they might still find their match in the rest and take precedence.
+    pub async fn judge(
+        &mut self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+    ) -> Option<(HomoServiceStatus, Option<HomoServiceEvidence>)> {
+        // 前の検証器が決まっていなくても、後の検証器は読んだ分を進めておく
+        // 判定が出た検証器より後ろは結果に関わらないので見ない
+        for ((validator, state), verdict) in self
+            .validators
+            .iter()
+            .zip(&mut self.states)
+            .zip(&mut self.verdicts)
+        {
+            if verdict.is_some() {
+                break;
+            }
+            *verdict = validator.scan(response, content, state).await;
+        }
+
+        for (validator, verdict) in self.validators.iter().zip(&self.verdicts) {
+            match verdict {
+                Some(verdict) => return Some(judged(validator.as_ref(), verdict.clone())),
+                None if validator.reads_body() && !content.is_finished() => return None,
+                None => continue,
+            }
+        }
+        Some((HomoServiceStatus::Invalid, None))
+    }
+}
+
+/// Attaches the evidence to the verdict.
+fn judged(
+    validator: &dyn ValidateResponse,
+    verdict: Verdict,
+) -> (HomoServiceStatus, Option<HomoServiceEvidence>) {
+    let evidence = HomoServiceEvidence {
+        validator: validator.name().into(),
+        matched: verdict.matched,
+        offset: verdict.offset,
+    };
+    (verdict.status, Some(evidence))
+}
+
+impl fmt::Debug for ValidatorPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("ValidatorPipeline")
+            .field(&self.names())
+            .finish()
+    }
+}
@@ -0,0 +1,120 @@
+use super::{ScanState, ValidateResponse, Verdict};
+use crate::{
+    domain::{HomoServiceStatus, HttpResponse},
+    validation::{ResponseContent, TargetUrls},
+};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref REGEX_LOCATION_ASSIGN: Regex = Regex::new(
+        r#"(?:\b(?:window|document|self|top)\s*\.\s*)?\blocation(?:\s*\.\s*href)?\s*=\s*(?:"([^"]*)"|'([^']*)'|`([^`]*)`)"#
+    )
+    .unwrap();
+    static ref REGEX_LOCATION_CALL: Regex = Regex::new(
+        r#"\blocation\s*\.\s*(?:replace|assign)\s*\(\s*(?:"([^"]*)"|'([^']*)'|`([^`]*)`)\s*\)"#
+    )
+    .unwrap();
+}
+
+/// Validates the response based on redirects in its scripts.
+/// Recognizes assignments to `location` and calls of `location.replace()` or `location.assign()`.
+pub struct ResponseScriptValidator {
+    targets: Arc<TargetUrls>,
+}
+
+impl ResponseScriptValidator {
+    pub fn new(targets: Arc<TargetUrls>) -> ResponseScriptValidator {
+        ResponseScriptValidator { targets }
+    }
+
+    /// Finds the first redirect expression to one of the targets in the script.
+    /// Returns its byte offset in the script together.
+    fn find_redirect<'a>(&self, script: &'a str) -> Option<(usize, &'a str)> {
+        // 代入と呼び出しのどちらであっても、スクリプト中で最初のものを採る
+        REGEX_LOCATION_ASSIGN
+            .captures_iter(script)
+            .chain(REGEX_LOCATION_CALL.captures_iter(script))
+            .filter(|c| {
+                let literal = c.get(1).or_else(|| c.get(2)).or_else(|| c.get(3));
+                literal
+                    .map(|l| self.targets.matches_str(&l.as_str().replace("\\/", "/")))
+                    .unwrap_or(false)
+            })
+            .filter_map(|c| c.get(0))
+            .min_by_key(|m| m.start())
+            .map(|m| (m.start(), m.as_str()))
+    }
+}
+
+#[async_trait]
+impl ValidateResponse for ResponseScriptValidator {
+    fn name(&self) -> &str {
+        "script"
+    }
+
+    async fn validate(
+        &self,
+        response: &HttpResponse,
+        content: &ResponseContent,
+    ) -> Option<Verdict> {
+        self.scan(response, content, &mut ScanState::default())
+            .await
+    }
+
+    async fn scan(
+        &self,
+        _response: &HttpResponse,
+        content: &ResponseContent,
+        state: &mut ScanState,
+    ) -> Option<Verdict> {
+        let body = content.text()?;
+        let document = content.document();
+
+        // スクリプトはイベントハンドラより優先するので、見つかればその時点で確定する
+        let scripts = &document.scripts[state.scripts..];
+        state.scripts += scripts.len();
+        let from_scripts = scripts.iter().find_map(|script| {
+            let (index, found) = self.find_redirect(&script.text)?;
+            Some((found, Some(script.source_offset(body, index))))
+        });
+        if let Some((found, offset)) = from_scripts {
+            return Some(Verdict::new(HomoServiceStatus::RedirectScript).matched_at(found, offset));
+        }
+
+        // イベントハンドラは後続のスクリプトに負けうるので、本文を読み終えるまで保留する
+        let tags = &document.tags[state.tags..];
+        state.tags += tags.len();
+        if state.tentative.is_none() {
+            let mut from_handlers = tags.iter().flat_map(|tag| {
+                tag.attributes
+                    .iter()
+                    .filter(|(name, _)| name.starts_with("on"))
+                    .filter_map(move |(name, value)| {
+                        let (index, found) = self.find_redirect(value)?;
+                        // 文字参照を含む値は位置を対応付けられないので値の先頭を指す
+                        let offset = tag.attribute_offset(body, name).map(|start| {
+                            if body[start..].starts_with(&value[..]) {
+                                start + index
+                            } else {
+                                start
+                            }
+                        });
+                        Some((found, offset))
+                    })
+            });
+            state.tentative = from_handlers.next().map(|(found, offset)| {
+                Verdict::new(HomoServiceStatus::RedirectScript).matched_at(found, offset)
+            });
+        }
+
+        if content.is_finished() {
+            state.tentative.take()
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Contains predicates comparing where redirects go.
+
+use url::{Host, Url};
+
+/// The second-level labels commonly registered under country code TLDs, like `co.jp` and `ac.uk`.
+const COUNTRY_SECOND_LEVELS: &[&str] = &[
+    "ac", "co", "com", "ed", "edu", "go", "gov", "gr", "lg", "ne", "net", "or", "org",
+];
+
+/// Returns the registrable domain of the URL, e.g. `example.co.jp` for `www.example.co.jp`.
+/// This approximates the Public Suffix List with common second-level domains of ccTLDs.
+/// IP addresses are returned as they are.
+pub fn registrable_domain(url: &Url) -> Option<&str> {
+    let domain = match url.host() {
+        Some(Host::Domain(domain)) => domain.trim_end_matches('.'),
+        Some(_) => return url.host_str(),
+        None => return None,
+    };
+
+    let labels: Vec<_> = domain.rsplitn(4, '.').collect();
+    let length = match labels.as_slice() {
+        [tld, second, _, ..] if tld.len() == 2 && COUNTRY_SECOND_LEVELS.contains(second) => 3,
+        _ => 2,
+    };
+    let suffix: usize = labels
+        .iter()
+        .take(length)
+        .map(|label| label.len() + 1)
+        .sum();
+    Some(&domain[(domain.len() + 1).saturating_sub(suffix)..])
+}
+
+/// Checks whether the URLs belong to the same registrable domain.
+pub fn is_same_site(a: &Url, b: &Url) -> bool {
+    registrable_domain(a) == registrable_domain(b)
+}
+
+/// Checks whether the redirect leaves HTTPS for plain HTTP.
+pub fn is_downgrade(from: &Url, to: &Url) -> bool {
+    from.scheme() == "https" && to.scheme() == "http"
+}
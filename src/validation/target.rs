@@ -0,0 +1,116 @@
+use std::{error::Error, str::FromStr};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use url::Url;
+
+lazy_static! {
+    static ref REGEX_URL_LIKE: Regex = Regex::new(r#"https?://[^\s"'<>\\]+"#).unwrap();
+}
+
+/// The target URLs used when nothing is configured.
+const DEFAULT_TARGET_URLS: &[&str] = &[
+    "https://twitter.com/mpyw",
+    "https://mobile.twitter.com/mpyw",
+    "https://x.com/mpyw",
+];
+
+/// Represents the set of URLs which homo services should point to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetUrls(Vec<Url>);
+
+impl TargetUrls {
+    /// Constructs from URLs. Trailing slashes in paths are ignored.
+    pub fn new(urls: impl IntoIterator<Item = Url>) -> TargetUrls {
+        let urls = urls
+            .into_iter()
+            .map(|mut url| {
+                let path = url.path().trim_end_matches('/').to_owned();
+                url.set_path(&path);
+                url.set_query(None);
+                url.set_fragment(None);
+                url
+            })
+            .collect();
+        TargetUrls(urls)
+    }
+
+    /// Returns the target URLs.
+    pub fn urls(&self) -> &[Url] {
+        &self.0
+    }
+
+    /// Checks whether the URL points to one of the targets.
+    /// Trailing paths, query strings and fragments are accepted.
+    pub fn matches(&self, url: &Url) -> bool {
+        self.0.iter().any(|target| {
+            if target.scheme() != url.scheme()
+                || target.host() != url.host()
+                || target.port_or_known_default() != url.port_or_known_default()
+            {
+                return false;
+            }
+
+            let target_path = target.path();
+            match url.path().strip_prefix(target_path) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/') || target_path == "/",
+                None => false,
+            }
+        })
+    }
+
+    /// Checks whether the string is a URL pointing to one of the targets.
+    pub fn matches_str(&self, url: &str) -> bool {
+        match Url::parse(url.trim()) {
+            Ok(url) => self.matches(&url),
+            Err(_) => false,
+        }
+    }
+
+    /// Finds the first URL in the text which points to one of the targets.
+    /// Returns its byte offset in the text together.
+    pub fn find_in<'a>(&self, text: &'a str) -> Option<(usize, &'a str)> {
+        REGEX_URL_LIKE
+            .find_iter(text)
+            .map(|m| {
+                let candidate = m
+                    .as_str()
+                    .trim_end_matches(&['.', ',', ';', ':', '!', '?', ')'][..]);
+                (m.start(), candidate)
+            })
+            .find(|(_, candidate)| self.matches_str(candidate))
+    }
+}
+
+impl Default for TargetUrls {
+    fn default() -> TargetUrls {
+        TargetUrls::new(
+            DEFAULT_TARGET_URLS
+                .iter()
+                .map(|url| Url::parse(url).unwrap()),
+        )
+    }
+}
+
+impl FromStr for TargetUrls {
+    type Err = Box<dyn Error + Send + Sync>;
+
+    /// Parses comma-separated URLs.
+    /// The scheme is assumed to be `https` when omitted.
+    fn from_str(list: &str) -> Result<TargetUrls, Self::Err> {
+        let mut urls = vec![];
+        for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let url = if entry.contains("://") {
+                Url::parse(entry)?
+            } else {
+                Url::parse(&format!("https://{}", entry))?
+            };
+            urls.push(url);
+        }
+
+        if urls.is_empty() {
+            return Err("No target URL specified".into());
+        }
+        Ok(TargetUrls::new(urls))
+    }
+}
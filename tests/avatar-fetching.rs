@@ -2,7 +2,11 @@ mod support;
 
 use self::support::{container::MockContainer, make_content_response};
 use homochecker_rs::{
-    action::fetch_avatar, domain::Provider, repository::Repositories, service::Services, Container,
+    action::fetch_avatar,
+    domain::Provider,
+    repository::Repositories,
+    service::{ServiceError, Services},
+    Container,
 };
 use std::sync::Arc;
 
@@ -15,7 +19,8 @@ async fn fetches_twitter_avatar() {
     let container = MockContainer::default();
     let for_twitter = container.services().avatar().for_twitter();
     let cache = container.repositories().avatar().source();
-    *(for_twitter.lock().await) = Box::new(move |_| make_content_response("text/html", &fixture));
+    *(for_twitter.lock().await) =
+        Box::new(move |_| Ok(make_content_response("text/html", &fixture)));
 
     let provider = Arc::new(Provider::Twitter("kb10uy".into()));
     let result = fetch_avatar(container.clone(), provider.clone()).await;
@@ -44,7 +49,7 @@ async fn fetches_mastodon_avatar() {
     let for_mastodon = container.services().avatar().for_mastodon();
     let cache = container.repositories().avatar().source();
     *(for_mastodon.lock().await) =
-        Box::new(move |_, _| make_content_response("application/json", &fixture));
+        Box::new(move |_, _| Ok(make_content_response("application/json", &fixture)));
 
     let provider = Arc::new(Provider::Mastodon {
         screen_name: "kb10uy".into(),
@@ -69,3 +74,27 @@ async fn fetches_mastodon_avatar() {
         "Successfully cached in AvatarRepository"
     );
 }
+
+#[async_test]
+async fn skips_missing_mastodon_user() {
+    let container = MockContainer::default();
+    let for_mastodon = container.services().avatar().for_mastodon();
+    let cache = container.repositories().avatar().source();
+    *(for_mastodon.lock().await) = Box::new(move |sn, domain| {
+        Err(ServiceError::NotFound(format!(
+            "https://{}/users/{}.json",
+            domain, sn
+        )))
+    });
+
+    let provider = Arc::new(Provider::Mastodon {
+        screen_name: "deleted".into(),
+        domain: "mstdn.maud.io".into(),
+    });
+
+    let result = fetch_avatar(container.clone(), provider.clone()).await;
+    assert_case!(result, None, "Missing user has no avatar");
+
+    let locked = cache.lock().await;
+    assert_case!(locked.get(&*provider), None, "Nothing is cached");
+}
@@ -0,0 +1,258 @@
+mod support;
+
+use self::support::{make_content_response, make_redirect_response, serve_raw, serve_text};
+use homochecker_rs::{
+    client::HttpClient,
+    domain::{HomoServiceError, HomoServiceErrorKind, HomoServiceStatus},
+    egress::EgressPolicy,
+    validation::{response::ValidatorPipeline, ResponseContent, TargetUrls},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use http::StatusCode;
+use tokio::{net::TcpListener, test as async_test};
+use url::Url;
+
+const HTML_HEAD_REDIRECT: &str = r#"<html>
+    <head>
+        <meta http-equiv="refresh" content="0;https://twitter.com/mpyw">
+"#;
+const HTML_BODY_LINK: &str = r#"<html>
+    <body>
+        <a href="https://twitter.com/mpyw">@mpyw</a>
+"#;
+
+fn local_client() -> HttpClient {
+    HttpClient::new(Duration::from_secs(1))
+        .unwrap()
+        .with_egress(EgressPolicy {
+            allowlist: vec!["127.0.0.1".parse().unwrap()],
+            ..Default::default()
+        })
+}
+
+fn pipeline() -> ValidatorPipeline {
+    let names = ["header", "html", "script", "link"];
+    ValidatorPipeline::from_names(names.iter().copied(), Arc::new(TargetUrls::default())).unwrap()
+}
+
+/// Serves the head of HTML and never finishes the body.
+async fn serve_endless(head: &str) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n{}",
+        head
+    );
+    serve_raw(listener, response.into_bytes(), Duration::from_secs(10));
+    Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap()
+}
+
+#[async_test]
+async fn truncates_large_body() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    serve_text(listener, "a".repeat(256 * 1024));
+    let url = Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+
+    let response = local_client()
+        .with_max_body_size(1000)
+        .fetch(&url, None)
+        .await
+        .unwrap();
+    assert_case!(response.body.len(), 1000, "Body larger than the limit");
+
+    let response = local_client().fetch(&url, None).await.unwrap();
+    assert_case!(response.body.len(), 256 * 1024, "Body within the limit");
+}
+
+#[async_test]
+async fn stops_reading_decided_body() {
+    let url = serve_endless(HTML_HEAD_REDIRECT).await;
+
+    let started = Instant::now();
+    let response = local_client()
+        .with_validators(pipeline())
+        .fetch(&url, None)
+        .await
+        .unwrap();
+    assert_case!(
+        (
+            pipeline().validate(&response).await,
+            started.elapsed() < Duration::from_millis(500)
+        ),
+        (HomoServiceStatus::RedirectContent, true),
+        "Body decided by meta element"
+    );
+
+    let kind = local_client()
+        .fetch(&url, None)
+        .await
+        .err()
+        .and_then(|e| e.into_cause().downcast::<HomoServiceError>().ok())
+        .map(|e| e.kind);
+    assert_case!(
+        kind,
+        Some(HomoServiceErrorKind::Timeout),
+        "Body read without validators"
+    );
+}
+
+#[async_test]
+async fn keeps_reading_undecided_body() {
+    // link より前の html が後続の本文で判定しうるので読み続ける
+    let url = serve_endless(HTML_BODY_LINK).await;
+
+    let kind = local_client()
+        .with_validators(pipeline())
+        .fetch(&url, None)
+        .await
+        .err()
+        .and_then(|e| e.into_cause().downcast::<HomoServiceError>().ok())
+        .map(|e| e.kind);
+    assert_case!(
+        kind,
+        Some(HomoServiceErrorKind::Timeout),
+        "Body matched only by a later validator"
+    );
+}
+
+#[async_test]
+async fn judges_partial_response() {
+    let pipeline = pipeline();
+
+    let response = make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw");
+    assert_case!(
+        pipeline.judge_header(&response).await.map(|j| j.0),
+        Some(HomoServiceStatus::RedirectResponse),
+        "Response decided by the header"
+    );
+
+    let response = make_content_response("text/html", HTML_HEAD_REDIRECT);
+    assert_case!(
+        pipeline.judge_header(&response).await,
+        None,
+        "Response not decided by the header"
+    );
+    assert_case!(
+        pipeline.judge_partial(&response).await.map(|j| j.0),
+        Some(HomoServiceStatus::RedirectContent),
+        "Partial response decided by the first body validator"
+    );
+
+    let response = make_content_response("text/html", HTML_BODY_LINK);
+    assert_case!(
+        pipeline.judge_partial(&response).await,
+        None,
+        "Partial response matched only by a later validator"
+    );
+    assert_case!(
+        pipeline.judge(&response).await.0,
+        HomoServiceStatus::LinkContent,
+        "Whole response matched by a later validator"
+    );
+}
+
+#[async_test]
+async fn scans_body_in_chunks() {
+    let pipeline = pipeline();
+    let mut response = make_content_response("text/html; charset=utf-8", "");
+    let mut content = ResponseContent::new(&response);
+    let mut scan = pipeline.scan();
+
+    let (head, rest) = HTML_HEAD_REDIRECT.split_at(HTML_HEAD_REDIRECT.find("0;").unwrap());
+    content.feed(head.as_bytes());
+    response.body.extend_from_slice(head.as_bytes());
+    assert_case!(
+        scan.judge(&response, &content).await,
+        None,
+        "Unclosed meta element is not looked at"
+    );
+
+    content.feed(rest.as_bytes());
+    response.body.extend_from_slice(rest.as_bytes());
+    assert_case!(
+        scan.judge(&response, &content).await.map(|j| j.0),
+        Some(HomoServiceStatus::RedirectContent),
+        "Closed meta element decides the status"
+    );
+}
+
+#[async_test]
+async fn holds_undecidable_verdicts() {
+    let pipeline =
+        ValidatorPipeline::from_names(vec!["script"], Arc::new(TargetUrls::default())).unwrap();
+    let mut response = make_content_response("text/html; charset=utf-8", "");
+    let mut content = ResponseContent::new(&response);
+    let mut scan = pipeline.scan();
+
+    let handler = r#"<body onload="location.href = 'https://twitter.com/mpyw'">"#;
+    content.feed(handler.as_bytes());
+    response.body.extend_from_slice(handler.as_bytes());
+    assert_case!(
+        scan.judge(&response, &content).await,
+        None,
+        "Event handler is held since a later script takes precedence"
+    );
+
+    let script = r#"<script>location.replace("https://twitter.com/mpyw/")</script>"#;
+    content.feed(script.as_bytes());
+    response.body.extend_from_slice(script.as_bytes());
+    assert_case!(
+        scan.judge(&response, &content)
+            .await
+            .and_then(|j| j.1)
+            .and_then(|e| e.matched),
+        Some(r#"location.replace("https://twitter.com/mpyw/")"#.into()),
+        "Later script decides the status"
+    );
+
+    let mut content = ResponseContent::new(&response);
+    let mut scan = pipeline.scan();
+    content.feed(handler.as_bytes());
+    content.finish();
+    assert_case!(
+        scan.judge(&response, &content)
+            .await
+            .and_then(|j| j.1)
+            .and_then(|e| e.matched),
+        Some("location.href = 'https://twitter.com/mpyw'".into()),
+        "Held event handler decides the status at the end"
+    );
+}
+
+#[async_test]
+async fn holds_mentions_at_the_end() {
+    let pipeline =
+        ValidatorPipeline::from_names(vec!["mention"], Arc::new(TargetUrls::default())).unwrap();
+    let response = make_content_response("text/plain; charset=utf-8", "");
+    let mut content = ResponseContent::new(&response);
+    let mut scan = pipeline.scan();
+
+    content.feed(b"see https://twitter.com/mpyw");
+    assert_case!(
+        scan.judge(&response, &content).await,
+        None,
+        "URL at the end might continue"
+    );
+
+    content.feed(b"x and https://twitter.com/mpyw");
+    assert_case!(
+        scan.judge(&response, &content).await,
+        None,
+        "Continued URL does not match"
+    );
+
+    content.feed(b" too");
+    assert_case!(
+        scan.judge(&response, &content)
+            .await
+            .and_then(|j| j.1)
+            .and_then(|e| e.offset),
+        Some(34),
+        "Terminated URL matches"
+    );
+}
@@ -0,0 +1,126 @@
+mod support;
+
+use self::support::{container::MockContainer, make_redirect_response, wait_for_saving};
+use homochecker_rs::{
+    action::{request_service, request_service_cached},
+    config::{CacheConfig, Config},
+    domain::{HomoServiceStatus, RedirectHop},
+    repository::{CachedCheckResult, Repositories},
+    service::Services,
+    Container,
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::Utc;
+use http::StatusCode;
+use tokio::test as async_test;
+use url::Url;
+
+/// Makes the container whose service redirects to the target, counting requests.
+async fn counting_container(config: Config) -> (MockContainer, Arc<AtomicUsize>) {
+    let container = MockContainer {
+        config: Arc::new(config),
+        ..Default::default()
+    };
+    let counter = Arc::new(AtomicUsize::new(0));
+    let source = container.services().homo_request().source();
+    let requests = counter.clone();
+    *(source.lock().await) = Box::new(move || {
+        requests.fetch_add(1, Ordering::SeqCst);
+        Ok((
+            make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw"),
+            Duration::from_millis(100),
+        ))
+    });
+
+    (container, counter)
+}
+
+#[async_test]
+async fn serves_cached_results() {
+    let (container, counter) = counting_container(Config::default()).await;
+    let service_url = Url::parse("https://example.org").unwrap();
+
+    let (response, cached_at) =
+        request_service_cached(container.clone(), service_url.clone(), false).await;
+    assert_case!(
+        (response.status, cached_at, counter.load(Ordering::SeqCst)),
+        (HomoServiceStatus::RedirectResponse, None, 1),
+        "First check requests to the service"
+    );
+    let cache = container.repositories().check_result().source();
+    wait_for_saving(&cache, |results| results.contains_key(&service_url)).await;
+
+    let (response, cached_at) =
+        request_service_cached(container.clone(), service_url.clone(), false).await;
+    assert_case!(
+        (
+            response.status,
+            cached_at.is_some(),
+            counter.load(Ordering::SeqCst)
+        ),
+        (HomoServiceStatus::RedirectResponse, true, 1),
+        "Second check is served from the cache"
+    );
+
+    let (_, cached_at) = request_service_cached(container.clone(), service_url, true).await;
+    assert_case!(
+        (cached_at, counter.load(Ordering::SeqCst)),
+        (None, 2),
+        "Fresh check bypasses the cache"
+    );
+}
+
+#[async_test]
+async fn disables_cache_by_zero_ttl() {
+    let config = Config {
+        cache: CacheConfig {
+            ttl: Duration::default(),
+        },
+        ..Default::default()
+    };
+    let (container, counter) = counting_container(config).await;
+    let service_url = Url::parse("https://example.org").unwrap();
+
+    request_service(container.clone(), service_url.clone()).await;
+    // 結果のキャッシュは履歴と一緒に保存される
+    let history = container.repositories().check_history().source();
+    wait_for_saving(&history, |records| !records.is_empty()).await;
+    let (_, cached_at) = request_service_cached(container.clone(), service_url, false).await;
+    let source = container.repositories().check_result().source();
+    assert_case!(
+        (
+            cached_at,
+            counter.load(Ordering::SeqCst),
+            source.lock().await.len()
+        ),
+        (None, 2, 0),
+        "Check without cache"
+    );
+}
+
+#[async_test]
+async fn serializes_cached_results() {
+    let (container, _) = counting_container(Config::default()).await;
+    let mut response = request_service(container, Url::parse("https://example.org").unwrap()).await;
+    response.redirects = vec![RedirectHop {
+        url: Url::parse("http://example.org").unwrap(),
+        status: StatusCode::MOVED_PERMANENTLY,
+        location: Some("https://example.org".into()),
+        duration: Duration::from_millis(50),
+    }];
+
+    let cached = CachedCheckResult {
+        response,
+        cached_at: Utc::now(),
+    };
+    let json = serde_json::to_string(&cached).unwrap();
+    let restored: CachedCheckResult = serde_json::from_str(&json).unwrap();
+    assert_case!(restored, cached, "Cached result survives serialization");
+}
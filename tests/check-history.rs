@@ -0,0 +1,147 @@
+mod support;
+
+use self::support::{container::MockContainer, make_redirect_response, wait_for_saving};
+use homochecker_rs::{
+    action::{request_service, request_service_cached},
+    api::data::HistoryQueryParameter,
+    domain::{HomoServiceError, HomoServiceErrorKind},
+    repository::{CheckHistoryRepository, Repositories},
+    service::Services,
+    Container,
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use chrono::Utc;
+use http::StatusCode;
+use tokio::test as async_test;
+use url::Url;
+
+#[async_test]
+async fn records_check_results() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+    // 記録がどのチェックのものか分かるように、リクエストごとに時間を変える
+    let requests = AtomicU64::new(0);
+    *(source.lock().await) = Box::new(move || {
+        let count = requests.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok((
+            make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw"),
+            Duration::from_millis(100 * count),
+        ))
+    });
+    let service_url = Url::parse("https://example.org").unwrap();
+    let history = container.repositories().check_history().source();
+
+    let started = Utc::now();
+    request_service_cached(container.clone(), service_url.clone(), false).await;
+    wait_for_saving(&history, |records| !records.is_empty()).await;
+    request_service_cached(container.clone(), service_url.clone(), false).await;
+    request_service_cached(container.clone(), service_url.clone(), true).await;
+    wait_for_saving(&history, |records| {
+        records
+            .iter()
+            .any(|r| r.duration == Duration::from_millis(200))
+    })
+    .await;
+    let records = history.lock().await.clone();
+    assert_case!(
+        records
+            .iter()
+            .map(|r| (
+                r.service_url.clone(),
+                r.status.as_str(),
+                r.duration,
+                r.error,
+                r.checked_at >= started
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                service_url.clone(),
+                "OK",
+                Duration::from_millis(100),
+                None,
+                true
+            ),
+            (
+                service_url.clone(),
+                "OK",
+                Duration::from_millis(200),
+                None,
+                true
+            )
+        ],
+        "Results served from the cache are not recorded, while fresh check is"
+    );
+    assert_case!(
+        container
+            .repositories()
+            .check_history()
+            .count_by_urls(&[service_url])
+            .await
+            .unwrap(),
+        2,
+        "Recorded checks are counted"
+    );
+}
+
+#[async_test]
+async fn records_failed_checks() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+    *(source.lock().await) = Box::new(|| {
+        let error = HomoServiceError::new(HomoServiceErrorKind::Timeout, "timed out");
+        Err(error.into())
+    });
+
+    request_service(
+        container.clone(),
+        Url::parse("https://example.org").unwrap(),
+    )
+    .await;
+    let history = container.repositories().check_history().source();
+    wait_for_saving(&history, |records| !records.is_empty()).await;
+    let record = history.lock().await.pop();
+    assert_case!(
+        record.map(|r| (r.status, r.ip, r.error)),
+        Some(("ERROR".into(), None, Some(HomoServiceErrorKind::Timeout))),
+        "Failed check is recorded with its error kind"
+    );
+}
+
+#[test]
+fn clamps_history_pages() {
+    let query = |page, per_page| HistoryQueryParameter { page, per_page };
+    assert_case!(
+        (query(None, None).page(), query(None, None).per_page()),
+        (1, HistoryQueryParameter::DEFAULT_PER_PAGE),
+        "Default page"
+    );
+    assert_case!(query(Some(3), Some(10)).offset(), 20, "Offset of the page");
+    assert_case!(
+        (
+            query(Some(0), Some(0)).page(),
+            query(Some(0), Some(0)).per_page()
+        ),
+        (1, 1),
+        "Page and per_page start from 1"
+    );
+
+    let huge = query(Some(usize::MAX), Some(usize::MAX));
+    assert_case!(
+        (
+            huge.page(),
+            huge.per_page(),
+            huge.offset() <= i64::MAX as usize
+        ),
+        (
+            HistoryQueryParameter::MAX_PAGE,
+            HistoryQueryParameter::MAX_PER_PAGE,
+            true
+        ),
+        "Huge page does not overflow"
+    );
+}
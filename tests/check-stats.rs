@@ -0,0 +1,192 @@
+mod support;
+
+use self::support::container::MockContainer;
+use homochecker_rs::{
+    action::summarize_services,
+    api::data::StatsJsonResponseWindow,
+    config::{Config, StatsConfig, StatsWindow},
+    repository::{is_down, CheckSummary, LatencyPercentiles, Repositories},
+    Container,
+};
+use std::{sync::Arc, time::Duration};
+
+use chrono::{Duration as ChronoDuration, Utc};
+use tokio::test as async_test;
+use url::Url;
+
+fn summary(service_url: &Url) -> CheckSummary {
+    CheckSummary {
+        service_url: service_url.clone(),
+        checks: 4,
+        up: 3,
+        latency: Some(LatencyPercentiles {
+            p50: Duration::from_millis(200),
+            p95: Duration::from_millis(290),
+            p99: Duration::from_millis(298),
+        }),
+        statuses: vec![("OK".into(), 2), ("WRONG".into(), 1), ("ERROR".into(), 1)]
+            .into_iter()
+            .collect(),
+    }
+}
+
+#[test]
+fn parses_stats_windows() {
+    let windows: Vec<_> = ["24h", "7d", "90m", "30s"]
+        .iter()
+        .map(|w| {
+            w.parse::<StatsWindow>()
+                .map(|w| (w.name, w.duration.as_secs()))
+        })
+        .collect();
+    assert_case!(
+        windows,
+        vec![
+            Ok(("24h".into(), 86400)),
+            Ok(("7d".into(), 604800)),
+            Ok(("90m".into(), 5400)),
+            Ok(("30s".into(), 30))
+        ],
+        "Valid windows"
+    );
+
+    assert_case!(
+        ["0h", "h", "24", "1w", ""]
+            .iter()
+            .map(|w| w.parse::<StatsWindow>().is_err())
+            .collect::<Vec<_>>(),
+        vec![true; 5],
+        "Invalid windows"
+    );
+
+    let now = Utc::now();
+    let window: StatsWindow = "7d".parse().unwrap();
+    assert_case!(
+        window.since(now),
+        now - ChronoDuration::days(7),
+        "Window ending at now"
+    );
+
+    let window: StatsWindow = "100000000d".parse().unwrap();
+    assert_case!(
+        window.since(Utc::now()).timestamp(),
+        0,
+        "Window reaching before the epoch"
+    );
+}
+
+#[test]
+fn summarizes_check_records() {
+    let service_url = Url::parse("https://example.org").unwrap();
+    let checks = |checks: &[(&str, u64)]| {
+        let checks: Vec<_> = checks
+            .iter()
+            .map(|(status, millis)| (status.to_string(), Duration::from_millis(*millis)))
+            .collect();
+        CheckSummary::summarize(service_url.clone(), checks)
+    };
+
+    assert_case!(
+        ["OK", "WRONG", "LOOP", "ERROR", "BLOCKED"]
+            .iter()
+            .map(|status| is_down(status))
+            .collect::<Vec<_>>(),
+        vec![false, false, false, true, true],
+        "Only unreachable statuses are down"
+    );
+    assert_case!(
+        checks(&[("OK", 300), ("ERROR", 5000), ("WRONG", 100), ("OK", 200)]),
+        summary(&service_url),
+        "Latency is interpolated over the checks which are up"
+    );
+    assert_case!(
+        checks(&[("MENTION", 150)]).latency,
+        Some(LatencyPercentiles {
+            p50: Duration::from_millis(150),
+            p95: Duration::from_millis(150),
+            p99: Duration::from_millis(150),
+        }),
+        "Latency of a single check"
+    );
+
+    let down = checks(&[("ERROR", 5000), ("BLOCKED", 0)]);
+    assert_case!(
+        (down.checks, down.up, down.latency, down.valid()),
+        (2, 0, None, 0),
+        "Services which are always down have no latency"
+    );
+}
+
+#[async_test]
+async fn summarizes_each_window() {
+    let container = MockContainer {
+        config: Arc::new(Config {
+            stats: StatsConfig {
+                windows: vec!["24h".parse().unwrap(), "7d".parse().unwrap()],
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let service_url = Url::parse("https://example.org").unwrap();
+    let other_url = Url::parse("https://example.net").unwrap();
+    let history = container.repositories().check_history();
+    *(history.summaries().lock().await) = vec![summary(&service_url), summary(&other_url)];
+
+    let service_urls = vec![service_url.clone()];
+    let windows = summarize_services(container, &service_urls).await.unwrap();
+    let now = Utc::now();
+    assert_case!(
+        windows
+            .iter()
+            .map(|(window, summaries)| (window.name.as_str(), summaries.clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("24h", vec![summary(&service_url)]),
+            ("7d", vec![summary(&service_url)]),
+        ],
+        "Summaries of the services are returned for each window"
+    );
+    assert_case!(
+        history
+            .summarized()
+            .lock()
+            .await
+            .iter()
+            .map(|since| (now - *since).num_hours())
+            .collect::<Vec<_>>(),
+        vec![24, 24 * 7],
+        "Each window is aggregated from its start"
+    );
+}
+
+#[test]
+fn builds_stats_windows() {
+    let window: StatsWindow = "24h".parse().unwrap();
+    let service_url = Url::parse("https://example.org").unwrap();
+
+    let built = StatsJsonResponseWindow::build(&window, Some(&summary(&service_url)));
+    assert_case!(
+        (
+            built.window.as_str(),
+            built.checks,
+            built.uptime,
+            built.validity,
+            built.latency.map(|l| l.p50)
+        ),
+        ("24h", 4, Some(75.0), Some(50.0), Some(0.2)),
+        "Window with checks"
+    );
+
+    let built = StatsJsonResponseWindow::build(&window, None);
+    assert_case!(
+        (
+            built.checks,
+            built.uptime,
+            built.validity,
+            built.latency.is_none()
+        ),
+        (0, None, None, true),
+        "Window without checks"
+    );
+}
@@ -0,0 +1,121 @@
+mod support;
+
+use self::support::{blackhole, serve_text};
+use homochecker_rs::{
+    client::HttpClient,
+    domain::IpVersion,
+    egress::{EgressPolicy, IpRange},
+};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener as StdTcpListener},
+    time::{Duration, Instant},
+};
+
+use tokio::{net::TcpListener, test as async_test};
+use url::Url;
+
+const V4: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+const V6: IpAddr = IpAddr::V6(Ipv6Addr::LOCALHOST);
+
+/// Allows requests to local listeners.
+fn allow_loopback() -> EgressPolicy {
+    let allowlist: Vec<IpRange> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+    EgressPolicy {
+        allowlist,
+        ..Default::default()
+    }
+}
+
+/// Binds 127.0.0.1 and ::1 on the same port.
+async fn bind_dual_stack() -> (TcpListener, TcpListener) {
+    loop {
+        let v4 = TcpListener::bind(SocketAddr::new(V4, 0)).await.unwrap();
+        let port = v4.local_addr().unwrap().port();
+        if let Ok(v6) = TcpListener::bind(SocketAddr::new(V6, port)).await {
+            return (v4, v6);
+        }
+    }
+}
+
+#[async_test]
+async fn requests_over_each_ip_version() {
+    let (v4, v6) = bind_dual_stack().await;
+    let port = v4.local_addr().unwrap().port();
+    serve_text(v4, "v4");
+    serve_text(v6, "v6");
+
+    let client = HttpClient::new(Duration::from_secs(5))
+        .unwrap()
+        .with_host("homo.test", vec![V4, V6])
+        .with_egress(allow_loopback());
+    let url = Url::parse(&format!("http://homo.test:{}/", port)).unwrap();
+
+    let response = client.fetch(&url, Some(IpVersion::V4)).await.unwrap();
+    assert_case!(
+        (response.body, response.remote_address),
+        (b"v4".to_vec(), Some(SocketAddr::new(V4, port))),
+        "Request over IPv4"
+    );
+
+    let response = client.fetch(&url, Some(IpVersion::V6)).await.unwrap();
+    assert_case!(
+        (response.body, response.remote_address),
+        (b"v6".to_vec(), Some(SocketAddr::new(V6, port))),
+        "Request over IPv6"
+    );
+
+    let response = client.fetch(&url, None).await.unwrap();
+    assert_case!(
+        response.body,
+        b"v4".to_vec(),
+        "Request over the first address"
+    );
+}
+
+#[async_test]
+async fn fails_without_address_of_ip_version() {
+    let client = HttpClient::new(Duration::from_secs(5))
+        .unwrap()
+        .with_host("homo.test", vec![V4]);
+    let url = Url::parse("http://homo.test/").unwrap();
+
+    let result = client.fetch(&url, Some(IpVersion::V6)).await;
+    assert_case!(result.is_err(), true, "Request over IPv6 to IPv4-only host");
+}
+
+#[async_test]
+async fn falls_back_from_unresponsive_address() {
+    let unresponsive: IpAddr = "127.0.0.2".parse().unwrap();
+    let (hole, listener) = loop {
+        let hole = StdTcpListener::bind(SocketAddr::new(unresponsive, 0)).unwrap();
+        let port = hole.local_addr().unwrap().port();
+        if let Ok(listener) = TcpListener::bind(SocketAddr::new(V4, port)).await {
+            break (hole, listener);
+        }
+    };
+    let port = listener.local_addr().unwrap().port();
+    let _hole = blackhole(hole);
+    serve_text(listener, "v4");
+
+    let client = HttpClient::new(Duration::from_secs(2))
+        .unwrap()
+        .with_host("homo.test", vec![unresponsive, V4])
+        .with_egress(EgressPolicy {
+            allowlist: vec!["127.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        });
+    let url = Url::parse(&format!("http://homo.test:{}/", port)).unwrap();
+
+    let started = Instant::now();
+    let response = client.fetch(&url, None).await.map(|r| r.remote_address);
+    assert_case!(
+        response.ok(),
+        Some(Some(SocketAddr::new(V4, port))),
+        "Request falls back to the next address"
+    );
+    assert_case!(
+        started.elapsed() < Duration::from_millis(1500),
+        true,
+        "Unresponsive address does not consume the whole timeout"
+    );
+}
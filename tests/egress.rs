@@ -0,0 +1,201 @@
+mod support;
+
+use self::support::{container::MockContainer, serve_text};
+use homochecker_rs::{
+    action::request_service,
+    client::HttpClient,
+    domain::{HomoServiceError, HomoServiceErrorKind, HomoServiceStatus},
+    egress::{is_global, EgressPolicy, IpRange},
+    service::Services,
+    Container,
+};
+use std::{net::IpAddr, time::Duration};
+
+use tokio::{net::TcpListener, test as async_test};
+use url::Url;
+
+fn ip(address: &str) -> IpAddr {
+    address.parse().unwrap()
+}
+
+#[test]
+fn parses_ip_ranges() {
+    let range: IpRange = "10.0.0.0/8".parse().unwrap();
+    assert_case!(
+        (
+            range.contains(ip("10.1.2.3")),
+            range.contains(ip("11.0.0.1"))
+        ),
+        (true, false),
+        "IPv4 range"
+    );
+
+    let range: IpRange = "fe80::/10".parse().unwrap();
+    assert_case!(
+        (range.contains(ip("fe80::1")), range.contains(ip("fec0::1"))),
+        (true, false),
+        "IPv6 range"
+    );
+
+    let range: IpRange = "127.0.0.1".parse().unwrap();
+    assert_case!(
+        (
+            range.contains(ip("127.0.0.1")),
+            range.contains(ip("127.0.0.2"))
+        ),
+        (true, false),
+        "Single address"
+    );
+
+    assert_case!(
+        (
+            "10.0.0.0/33".parse::<IpRange>().is_err(),
+            "localhost".parse::<IpRange>().is_err()
+        ),
+        (true, true),
+        "Invalid ranges"
+    );
+}
+
+#[test]
+fn classifies_global_addresses() {
+    let non_global = [
+        "127.0.0.1",
+        "10.0.0.1",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "224.0.0.1",
+        "0.0.0.0",
+        "::1",
+        "fe80::1",
+        "fd00::1",
+        "ff02::1",
+        "::ffff:127.0.0.1",
+        "::127.0.0.1",
+        "64:ff9b::a9fe:a9fe",
+        "64:ff9b:1::a00:1",
+        "100::1",
+        "2002:7f00:1::1",
+        "fec0::1",
+    ];
+    for address in &non_global {
+        assert_case!(is_global(ip(address)), false, "{} is not global", address);
+    }
+
+    let global = [
+        "93.184.216.34",
+        "172.32.0.1",
+        "2606:2800:220:1::1",
+        "2001:4860:4860::8888",
+        "64:ff9b::5db8:d822",
+    ];
+    for address in &global {
+        assert_case!(is_global(ip(address)), true, "{} is global", address);
+    }
+}
+
+#[test]
+fn checks_destinations() {
+    let policy = EgressPolicy::default();
+    assert_case!(
+        (
+            policy.check_url(&Url::parse("https://example.com").unwrap()),
+            policy
+                .check_url(&Url::parse("ftp://example.com").unwrap())
+                .is_err(),
+        ),
+        (Ok(()), true),
+        "Allowed and disallowed schemes"
+    );
+    assert_case!(
+        (
+            policy.check_destination(ip("93.184.216.34"), 443),
+            policy.check_destination(ip("93.184.216.34"), 6379).is_err(),
+            policy.check_destination(ip("127.0.0.1"), 80).is_err(),
+        ),
+        (Ok(()), true, true),
+        "Allowed and disallowed destinations"
+    );
+
+    let policy = EgressPolicy {
+        allowlist: vec!["127.0.0.0/8".parse().unwrap()],
+        ..Default::default()
+    };
+    assert_case!(
+        policy.check_destination(ip("127.0.0.1"), 6379),
+        Ok(()),
+        "Allowlisted destination"
+    );
+}
+
+#[async_test]
+async fn blocks_local_services() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    serve_text(listener, "internal");
+    let url = Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+
+    let client = HttpClient::new(Duration::from_secs(5)).unwrap();
+    let kind = client.fetch(&url, None).await.err().and_then(|e| {
+        e.into_cause()
+            .downcast::<HomoServiceError>()
+            .ok()
+            .map(|e| e.kind)
+    });
+    assert_case!(
+        kind,
+        Some(HomoServiceErrorKind::Blocked),
+        "Request to loopback address"
+    );
+
+    let client = HttpClient::new(Duration::from_secs(5))
+        .unwrap()
+        .with_host("metadata.test", vec![ip("169.254.169.254")]);
+    let url = Url::parse("http://metadata.test/latest/meta-data/").unwrap();
+    let kind = client.fetch(&url, None).await.err().and_then(|e| {
+        e.into_cause()
+            .downcast::<HomoServiceError>()
+            .ok()
+            .map(|e| e.kind)
+    });
+    assert_case!(
+        kind,
+        Some(HomoServiceErrorKind::Blocked),
+        "Request to link-local address resolved by name"
+    );
+
+    let client = HttpClient::new(Duration::from_secs(5))
+        .unwrap()
+        .with_egress(EgressPolicy {
+            allowlist: vec!["127.0.0.1".parse().unwrap()],
+            ..Default::default()
+        });
+    let url = Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+    let body = client.fetch(&url, None).await.ok().map(|r| r.body);
+    assert_case!(
+        body,
+        Some(b"internal".to_vec()),
+        "Request to allowlisted address"
+    );
+}
+
+#[async_test]
+async fn reports_blocked_service() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+    *(source.lock().await) = Box::new(|| {
+        let error = HomoServiceError::new(HomoServiceErrorKind::Blocked, "not global");
+        Err(error.into())
+    });
+
+    let result = request_service(container, Url::parse("http://example.org").unwrap()).await;
+    assert_case!(
+        (result.status, result.error.map(|e| e.kind)),
+        (
+            HomoServiceStatus::Blocked,
+            Some(HomoServiceErrorKind::Blocked)
+        ),
+        "Request for HomoService refused by egress policy"
+    );
+}
@@ -1,13 +1,26 @@
 mod support;
 
-use self::support::{container::MockContainer, make_homo_service, make_redirect_response};
+use self::support::{
+    container::MockContainer, make_content_response, make_homo_service, make_redirect_response,
+};
 use homochecker_rs::{
     action::{attach_avatar_resolver, request_service},
-    domain::{HomoServiceResponse, HomoServiceStatus, Provider},
-    service::Services,
+    config::{Config, RequestConfig, RetryConfig, MAX_RETRY_DELAY},
+    domain::{
+        HomoServiceError, HomoServiceErrorKind, HomoServiceEvidence, HomoServiceResponse,
+        HomoServiceStatus, IpVersion, Provider, RedirectError, RedirectHop, RequestTiming,
+    },
+    service::{ServiceError, Services},
+    validation::{is_downgrade, is_same_site, registrable_domain},
     Container,
 };
-use std::time::Duration;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use http::StatusCode;
 use tokio::test as async_test;
@@ -20,21 +33,29 @@ async fn requests_valid_service() {
 
     let service_url = Url::parse("https://example.org").unwrap();
     *(source.lock().await) = Box::new(|| {
-        (
+        Ok((
             make_redirect_response(StatusCode::MOVED_PERMANENTLY, "https://twitter.com/mpyw"),
             Duration::from_secs(1),
-        )
+        ))
     });
 
-    let result = request_service(container.clone(), service_url)
-        .await
-        .unwrap();
+    let result = request_service(container.clone(), service_url).await;
     assert_case!(
         result,
         HomoServiceResponse {
             duration: Duration::from_secs(1),
             remote_address: None,
             status: HomoServiceStatus::RedirectResponse,
+            evidence: Some(HomoServiceEvidence {
+                validator: "header".into(),
+                matched: Some("https://twitter.com/mpyw".into()),
+                offset: None,
+            }),
+            redirects: vec![],
+            error: None,
+            attempts: 1,
+            timing: None,
+            dual_stack: None,
         },
         "Request for HomoService succeeds"
     );
@@ -47,26 +68,362 @@ async fn requests_invalid_service() {
 
     let service_url = Url::parse("https://example.org").unwrap();
     *(source.lock().await) = Box::new(|| {
-        (
+        Ok((
             make_redirect_response(StatusCode::MOVED_PERMANENTLY, "https://twitter.com/kb10uy"),
             Duration::from_secs(0),
-        )
+        ))
     });
 
-    let result = request_service(container.clone(), service_url)
-        .await
-        .unwrap();
+    let result = request_service(container.clone(), service_url).await;
     assert_case!(
         result,
         HomoServiceResponse {
             duration: Duration::from_secs(0),
             remote_address: None,
             status: HomoServiceStatus::Invalid,
+            evidence: Some(HomoServiceEvidence {
+                validator: "header".into(),
+                matched: Some("https://twitter.com/kb10uy".into()),
+                offset: None,
+            }),
+            redirects: vec![],
+            error: None,
+            attempts: 1,
+            timing: None,
+            dual_stack: None,
         },
         "Request for HomoService succeeds"
     );
 }
 
+#[async_test]
+async fn requests_script_redirect_service() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+
+    let service_url = Url::parse("https://example.org").unwrap();
+    *(source.lock().await) = Box::new(|| {
+        Ok((
+            make_content_response(
+                "text/html",
+                r#"<script>location.href = "https://twitter.com/mpyw";</script>"#,
+            ),
+            Duration::from_secs(0),
+        ))
+    });
+
+    let result = request_service(container.clone(), service_url).await;
+    assert_case!(
+        result.status,
+        HomoServiceStatus::RedirectScript,
+        "Script redirect takes precedence over link content"
+    );
+    assert_case!(
+        result.evidence,
+        Some(HomoServiceEvidence {
+            validator: "script".into(),
+            matched: Some(r#"location.href = "https://twitter.com/mpyw""#.into()),
+            offset: Some(8),
+        }),
+        "Evidence of script redirect"
+    );
+}
+
+fn make_redirect_hops(urls: &[&str]) -> Vec<RedirectHop> {
+    urls.windows(2)
+        .map(|pair| RedirectHop {
+            url: Url::parse(pair[0]).unwrap(),
+            status: StatusCode::MOVED_PERMANENTLY,
+            location: Some(pair[1].into()),
+            duration: Duration::from_millis(100),
+        })
+        .collect()
+}
+
+#[async_test]
+async fn requests_redirecting_service() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+
+    let service_url = Url::parse("http://example.org").unwrap();
+    *(source.lock().await) = Box::new(|| {
+        let mut response = make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw");
+        response.url = Url::parse("https://example.org").unwrap();
+        response.redirects = make_redirect_hops(&["http://example.org", "https://example.org"]);
+        Ok((response, Duration::from_millis(200)))
+    });
+
+    let result = request_service(container.clone(), service_url).await;
+    assert_case!(
+        result.status,
+        HomoServiceStatus::RedirectResponse,
+        "Request for HomoService with same-domain redirect succeeds"
+    );
+    assert_case!(
+        result.redirects,
+        make_redirect_hops(&["http://example.org", "https://example.org"]),
+        "Redirect chain is exposed"
+    );
+}
+
+#[async_test]
+async fn requests_indirect_service() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+
+    let service_url = Url::parse("https://example.org").unwrap();
+    *(source.lock().await) = Box::new(|| {
+        let mut response = make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw");
+        response.url = Url::parse("https://short.example.net/homo").unwrap();
+        response.redirects =
+            make_redirect_hops(&["https://example.org", "https://short.example.net/homo"]);
+        Ok((response, Duration::from_millis(200)))
+    });
+
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        (result.status, result.redirects.len()),
+        (HomoServiceStatus::Indirect, 1),
+        "Request for HomoService redirecting via another domain"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let mut response = make_content_response(
+            "text/html",
+            r#"<a href="https://twitter.com/mpyw">mpyw</a>"#,
+        );
+        response.url = Url::parse("https://short.example.net/homo").unwrap();
+        response.redirects =
+            make_redirect_hops(&["https://example.org", "https://short.example.net/homo"]);
+        Ok((response, Duration::from_millis(200)))
+    });
+
+    let result = request_service(container.clone(), service_url).await;
+    assert_case!(
+        result.status,
+        HomoServiceStatus::LinkContent,
+        "Request for HomoService linking via another domain"
+    );
+}
+
+#[async_test]
+async fn requests_same_site_redirecting_service() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+
+    let service_url = Url::parse("https://example.co.jp").unwrap();
+    *(source.lock().await) = Box::new(|| {
+        let mut response = make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw");
+        response.url = Url::parse("https://www.example.co.jp/homo").unwrap();
+        response.redirects =
+            make_redirect_hops(&["https://example.co.jp", "https://www.example.co.jp/homo"]);
+        Ok((response, Duration::from_millis(200)))
+    });
+
+    let result = request_service(container.clone(), service_url).await;
+    assert_case!(
+        result.status,
+        HomoServiceStatus::RedirectResponse,
+        "Request for HomoService redirecting via its subdomain is not indirect"
+    );
+}
+
+#[test]
+fn compares_redirect_sites() {
+    let url = |s: &str| Url::parse(s).unwrap();
+    assert_case!(
+        registrable_domain(&url("https://www.example.co.jp/")),
+        Some("example.co.jp"),
+        "Second-level domains of ccTLDs are kept"
+    );
+    assert_case!(
+        registrable_domain(&url("https://a.b.example.com./")),
+        Some("example.com"),
+        "Subdomains and the trailing dot are dropped"
+    );
+    assert_case!(
+        is_same_site(&url("https://example.com"), &url("http://WWW.example.com")),
+        true,
+        "Subdomains belong to the same site"
+    );
+    assert_case!(
+        is_same_site(&url("https://example.co.jp"), &url("https://other.co.jp")),
+        false,
+        "Domains under the same ccTLD second level are different sites"
+    );
+    assert_case!(
+        is_downgrade(&url("https://example.com"), &url("http://example.com")),
+        true,
+        "HTTPS to HTTP is a downgrade"
+    );
+    assert_case!(
+        is_downgrade(&url("http://example.com"), &url("https://example.com")),
+        false,
+        "HTTP to HTTPS is not a downgrade"
+    );
+}
+
+#[async_test]
+async fn reports_request_timing() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+
+    let timing = RequestTiming {
+        queue: Duration::from_millis(0),
+        dns: Duration::from_millis(10),
+        connect: Duration::from_millis(20),
+        tls: Some(Duration::from_millis(30)),
+        ttfb: Duration::from_millis(40),
+        body: Duration::from_millis(50),
+    };
+    let service_url = Url::parse("https://example.org").unwrap();
+    *(source.lock().await) = Box::new(move || {
+        let mut response = make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw");
+        response.timing = Some(timing);
+        Ok((response, Duration::from_millis(150)))
+    });
+
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        result.timing,
+        Some(timing),
+        "Timing of the final response is reported"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let error = HomoServiceError::new(HomoServiceErrorKind::Dns, "dns error");
+        Err(error.into())
+    });
+    let result = request_service(container.clone(), service_url).await;
+    assert_case!(
+        result.timing,
+        None,
+        "Timing of failed request is not reported"
+    );
+}
+
+#[async_test]
+async fn requests_redirect_loop_service() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+
+    let service_url = Url::parse("https://example.org/a").unwrap();
+    *(source.lock().await) = Box::new(|| {
+        let hops = make_redirect_hops(&[
+            "https://example.org/a",
+            "https://example.org/b",
+            "https://example.org/a",
+        ]);
+        Err(RedirectError::Loop(hops).into())
+    });
+
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        (result.status, result.redirects.len(), result.duration),
+        (
+            HomoServiceStatus::RedirectLoop,
+            2,
+            Duration::from_millis(200)
+        ),
+        "Request for HomoService with redirect loop"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let hops = make_redirect_hops(&[
+            "https://example.org/1",
+            "https://example.org/2",
+            "https://example.org/3",
+        ]);
+        Err(RedirectError::TooMany(hops).into())
+    });
+
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        result.status,
+        HomoServiceStatus::TooManyRedirects,
+        "Request for HomoService with too many redirects"
+    );
+}
+
+#[async_test]
+async fn requests_failing_service() {
+    let container = MockContainer::default();
+    let source = container.services().homo_request().source();
+
+    let service_url = Url::parse("https://example.org").unwrap();
+    *(source.lock().await) = Box::new(|| {
+        let error = HomoServiceError::new(HomoServiceErrorKind::Dns, "dns error");
+        Err(error.into())
+    });
+
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        (result.status, result.error),
+        (
+            HomoServiceStatus::Error,
+            Some(HomoServiceError::new(
+                HomoServiceErrorKind::Dns,
+                "dns error"
+            ))
+        ),
+        "Request for HomoService with DNS failure"
+    );
+
+    *(source.lock().await) =
+        Box::new(|| Err(ServiceError::Upstream("Something went wrong".into())));
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        (result.status, result.error.map(|e| e.kind)),
+        (
+            HomoServiceStatus::Error,
+            Some(HomoServiceErrorKind::Unknown)
+        ),
+        "Request for HomoService with unclassified error"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let mut response = make_content_response("text/html", "<h1>Not Found</h1>");
+        response.status = StatusCode::NOT_FOUND;
+        Ok((response, Duration::from_millis(100)))
+    });
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        (result.status, result.error.map(|e| e.kind)),
+        (
+            HomoServiceStatus::Error,
+            Some(HomoServiceErrorKind::HttpClientError)
+        ),
+        "Request for HomoService returning 404"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let mut response = make_content_response("text/html", "<h1>Bad Gateway</h1>");
+        response.status = StatusCode::BAD_GATEWAY;
+        Ok((response, Duration::from_millis(100)))
+    });
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        result.error.map(|e| e.kind),
+        Some(HomoServiceErrorKind::HttpServerError),
+        "Request for HomoService returning 502"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let mut response = make_content_response(
+            "text/html",
+            r#"<meta http-equiv="refresh" content="0; url=https://twitter.com/mpyw">"#,
+        );
+        response.status = StatusCode::NOT_FOUND;
+        Ok((response, Duration::from_millis(100)))
+    });
+    let result = request_service(container.clone(), service_url).await;
+    assert_case!(
+        (result.status, result.error),
+        (HomoServiceStatus::RedirectContent, None),
+        "Request for HomoService redirecting by 404 page"
+    );
+}
+
 #[async_test]
 async fn attaches_url_broadcaster() {
     let url = Url::parse("https://kb10uy.org").unwrap();
@@ -84,3 +441,185 @@ async fn attaches_url_broadcaster() {
     let received = rx.recv().await.unwrap();
     assert_case!(received, Some(url), "Equals sent URL and received URL");
 }
+
+#[async_test]
+async fn retries_failing_service() {
+    let container = MockContainer {
+        config: Arc::new(Config {
+            retry: RetryConfig {
+                attempts: 3,
+                backoff: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let source = container.services().homo_request().source();
+    let service_url = Url::parse("https://example.org").unwrap();
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    *(source.lock().await) = Box::new(move || {
+        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            let error = HomoServiceError::new(HomoServiceErrorKind::Timeout, "timed out");
+            Err(error.into())
+        } else {
+            Ok((
+                make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw"),
+                Duration::from_millis(100),
+            ))
+        }
+    });
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        (result.status, result.attempts),
+        (HomoServiceStatus::RedirectResponse, 2),
+        "Request for HomoService succeeds after retry"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let error = HomoServiceError::new(HomoServiceErrorKind::Connection, "connection reset");
+        Err(error.into())
+    });
+    let result = request_service(container.clone(), service_url.clone()).await;
+    assert_case!(
+        (result.status, result.attempts),
+        (HomoServiceStatus::Error, 3),
+        "Request for HomoService gives up after all attempts"
+    );
+
+    *(source.lock().await) = Box::new(|| {
+        let error = HomoServiceError::new(HomoServiceErrorKind::Dns, "dns error");
+        Err(error.into())
+    });
+    let result = request_service(container.clone(), service_url).await;
+    assert_case!(
+        (result.status, result.attempts),
+        (HomoServiceStatus::Error, 1),
+        "Request for HomoService is not retried on non-retryable error"
+    );
+}
+
+#[async_test]
+async fn requests_dual_stack_service() {
+    let container = MockContainer {
+        config: Arc::new(Config {
+            request: RequestConfig {
+                dual_stack: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let source = container.services().homo_request().source_over();
+    let service_url = Url::parse("https://example.org").unwrap();
+
+    *(source.lock().await) = Box::new(|version| match version {
+        IpVersion::V4 => Ok((
+            make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw"),
+            Duration::from_millis(100),
+        )),
+        IpVersion::V6 => {
+            let error = HomoServiceError::new(HomoServiceErrorKind::Connection, "unreachable");
+            Err(error.into())
+        }
+    });
+    let result = request_service(container.clone(), service_url.clone()).await;
+    let dual_stack = *result.dual_stack.unwrap();
+    assert_case!(
+        (
+            result.status,
+            dual_stack.ipv4.status,
+            dual_stack.ipv6.status
+        ),
+        (
+            HomoServiceStatus::RedirectResponse,
+            HomoServiceStatus::RedirectResponse,
+            HomoServiceStatus::Error
+        ),
+        "Request for HomoService reachable only over IPv4"
+    );
+
+    *(source.lock().await) = Box::new(|version| match version {
+        IpVersion::V4 => {
+            let error = HomoServiceError::new(HomoServiceErrorKind::Dns, "No address found");
+            Err(error.into())
+        }
+        IpVersion::V6 => Ok((
+            make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw"),
+            Duration::from_millis(100),
+        )),
+    });
+    let result = request_service(container.clone(), service_url).await;
+    let dual_stack = *result.dual_stack.unwrap();
+    assert_case!(
+        (
+            result.status,
+            dual_stack.ipv4.status,
+            dual_stack.ipv6.status
+        ),
+        (
+            HomoServiceStatus::RedirectResponse,
+            HomoServiceStatus::Error,
+            HomoServiceStatus::RedirectResponse
+        ),
+        "Request for HomoService reachable only over IPv6"
+    );
+}
+
+#[test]
+fn computes_retry_delay() {
+    let retry = RetryConfig {
+        backoff: Duration::from_millis(500),
+        ..Default::default()
+    };
+    assert_case!(
+        (retry.delay(1), retry.delay(2), retry.delay(3)),
+        (
+            Duration::from_millis(500),
+            Duration::from_secs(1),
+            Duration::from_secs(2)
+        ),
+        "Backoff doubles on each retry"
+    );
+    assert_case!(
+        retry.delay(usize::MAX),
+        MAX_RETRY_DELAY,
+        "Delay after many attempts is capped"
+    );
+
+    let retry = RetryConfig {
+        backoff: Duration::from_millis(u64::MAX),
+        ..Default::default()
+    };
+    assert_case!(
+        (retry.delay(1), retry.delay(17)),
+        (MAX_RETRY_DELAY, MAX_RETRY_DELAY),
+        "Huge backoff does not overflow"
+    );
+}
+
+#[test]
+fn classifies_service_errors() {
+    let error: ServiceError = HomoServiceError::new(HomoServiceErrorKind::Timeout, "").into();
+    assert_case!(
+        matches!(error, ServiceError::Unavailable(_)),
+        true,
+        "Timeout is classified as unavailable"
+    );
+
+    let error: ServiceError = HomoServiceError::new(HomoServiceErrorKind::BodyDecode, "").into();
+    assert_case!(
+        matches!(error, ServiceError::InvalidData(_)),
+        true,
+        "Body decode error is classified as invalid data"
+    );
+
+    let error: ServiceError = RedirectError::Loop(vec![]).into();
+    assert_case!(
+        matches!(error, ServiceError::Upstream(_)),
+        true,
+        "Redirect loop is classified as upstream failure"
+    );
+}
@@ -0,0 +1,133 @@
+mod support;
+
+use self::support::serve_raw;
+use homochecker_rs::{client::HttpClient, egress::EgressPolicy, service::RequestLimiter};
+use std::{net::IpAddr, sync::Arc, time::Duration};
+
+use tokio::{
+    net::TcpListener,
+    spawn, test as async_test,
+    time::{delay_for, timeout},
+};
+use url::Url;
+
+const WAIT: Duration = Duration::from_millis(50);
+
+fn address(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+#[async_test]
+async fn limits_requests_per_destination() {
+    let limiter = RequestLimiter::new(4, 1);
+
+    let permit = limiter.acquire(address("192.0.2.1")).await;
+    let blocked = timeout(WAIT, limiter.acquire(address("192.0.2.1"))).await;
+    assert_case!(
+        blocked.is_err(),
+        true,
+        "Second request to same address waits"
+    );
+
+    let other = timeout(WAIT, limiter.acquire(address("192.0.2.2"))).await;
+    assert_case!(other.is_ok(), true, "Request to another address proceeds");
+
+    drop(permit);
+    let released = timeout(WAIT, limiter.acquire(address("192.0.2.1"))).await;
+    assert_case!(released.is_ok(), true, "Request proceeds after release");
+}
+
+#[async_test]
+async fn limits_requests_globally() {
+    let limiter = RequestLimiter::new(2, 2);
+
+    let first = limiter.acquire(address("192.0.2.1")).await;
+    let _second = limiter.acquire(address("192.0.2.2")).await;
+    assert_case!(limiter.available(), 0, "All permits are in use");
+
+    let blocked = timeout(WAIT, limiter.acquire(address("2001:db8::1"))).await;
+    assert_case!(blocked.is_err(), true, "Third request waits");
+    assert_case!(
+        limiter.available(),
+        0,
+        "Cancelled request does not leak permits"
+    );
+
+    drop(first);
+    assert_case!(limiter.available(), 1, "Permit is released on drop");
+    let released = timeout(WAIT, limiter.acquire(address("192.0.2.1"))).await;
+    assert_case!(released.is_ok(), true, "Request proceeds after release");
+}
+
+#[async_test]
+async fn forgets_idle_destinations() {
+    let limiter = RequestLimiter::new(4, 1);
+
+    let first = limiter.acquire(address("192.0.2.1")).await;
+    let second = limiter.acquire(address("192.0.2.2")).await;
+    assert_case!(limiter.destinations(), 2, "Destinations in use are tracked");
+
+    let blocked = timeout(WAIT, limiter.acquire(address("192.0.2.1"))).await;
+    assert_case!(blocked.is_err(), true, "Second request waits");
+    drop(second);
+    assert_case!(
+        limiter.destinations(),
+        1,
+        "Destination is forgotten when its last permit is released"
+    );
+
+    drop(first);
+    assert_case!(
+        limiter.destinations(),
+        0,
+        "Cancelled request does not leave its destination"
+    );
+}
+
+#[async_test]
+async fn limits_requests_to_shared_address() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let response = b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\nhomo".to_vec();
+    serve_raw(listener, response, Duration::from_millis(300));
+
+    let limiter = Arc::new(RequestLimiter::new(4, 1));
+    let client = HttpClient::new(Duration::from_millis(500))
+        .unwrap()
+        .with_egress(EgressPolicy {
+            allowlist: vec!["127.0.0.1".parse().unwrap()],
+            ..Default::default()
+        })
+        .with_host("a.example.com", vec![address("127.0.0.1")])
+        .with_host("b.example.com", vec![address("127.0.0.1")])
+        .with_limiter(limiter.clone());
+    let url_a = Url::parse(&format!("http://a.example.com:{}/", port)).unwrap();
+    let url_b = Url::parse(&format!("http://b.example.com:{}/", port)).unwrap();
+
+    let first = {
+        let client = client.clone();
+        spawn(async move { client.fetch(&url_a, None).await })
+    };
+    delay_for(WAIT).await;
+    assert_case!(
+        limiter.destinations(),
+        1,
+        "Destination is tracked by address"
+    );
+
+    let blocked = timeout(WAIT, client.fetch(&url_b, None)).await;
+    assert_case!(
+        blocked.is_err(),
+        true,
+        "Request to another host on the same address waits"
+    );
+
+    let second = client.fetch(&url_b, None).await;
+    assert_case!(first.await.unwrap().is_ok(), true, "First request succeeds");
+    assert_case!(
+        second.map(|r| r.body).ok(),
+        Some(b"homo".to_vec()),
+        "Time waiting for the limit is not counted in the timeout"
+    );
+    assert_case!(limiter.destinations(), 0, "Idle destination is forgotten");
+}
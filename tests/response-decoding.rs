@@ -0,0 +1,221 @@
+mod support;
+
+use self::support::{make_binary_response, serve_raw};
+use homochecker_rs::{
+    client::HttpClient,
+    domain::HomoServiceStatus,
+    egress::EgressPolicy,
+    validation::{
+        response::{ResponseHtmlValidator, ValidateResponseExt},
+        ResponseContent, TargetUrls,
+    },
+};
+use std::{sync::Arc, time::Duration};
+
+use encoding_rs::{EUC_JP, SHIFT_JIS};
+use tokio::{net::TcpListener, test as async_test};
+use url::Url;
+
+const HTML_JAPANESE_META_CHARSET: &str = r#"
+    <html>
+        <head>
+            <meta charset="Shift_JIS">
+            <title>ホモと申します</title>
+        </head>
+    </html>
+"#;
+const HTML_JAPANESE_META_HTTP_EQUIV: &str = r#"
+    <html>
+        <head>
+            <meta http-equiv="Content-Type" content="text/html; charset=EUC-JP">
+            <title>ホモと申します</title>
+        </head>
+    </html>
+"#;
+const HTML_JAPANESE_PLAIN: &str = r#"
+    <html>
+        <head>
+            <title>ホモと申します</title>
+        </head>
+    </html>
+"#;
+
+#[test]
+fn decodes_by_content_type() {
+    let (encoded, _, _) = SHIFT_JIS.encode(HTML_JAPANESE_PLAIN);
+    let response = make_binary_response(Some("text/html; charset=\"shift_jis\""), &encoded);
+    assert_case!(
+        response.text().as_deref(),
+        Some(HTML_JAPANESE_PLAIN),
+        "Decoding Shift_JIS body by Content-Type charset"
+    );
+
+    let (encoded, _, _) = EUC_JP.encode(HTML_JAPANESE_META_CHARSET);
+    let response = make_binary_response(Some("text/html; charset=euc-jp"), &encoded);
+    assert_case!(
+        response.text().as_deref(),
+        Some(HTML_JAPANESE_META_CHARSET),
+        "Decoding by Content-Type charset prior to meta charset"
+    );
+}
+
+#[test]
+fn decodes_by_meta_charset() {
+    let (encoded, _, _) = SHIFT_JIS.encode(HTML_JAPANESE_META_CHARSET);
+    let response = make_binary_response(Some("text/html"), &encoded);
+    assert_case!(
+        response.text().as_deref(),
+        Some(HTML_JAPANESE_META_CHARSET),
+        "Decoding Shift_JIS body by meta charset"
+    );
+
+    let (encoded, _, _) = EUC_JP.encode(HTML_JAPANESE_META_HTTP_EQUIV);
+    let response = make_binary_response(None, &encoded);
+    assert_case!(
+        response.text().as_deref(),
+        Some(HTML_JAPANESE_META_HTTP_EQUIV),
+        "Decoding EUC-JP body by meta http-equiv without Content-Type"
+    );
+}
+
+#[test]
+fn decodes_by_bom_or_default() {
+    let mut encoded = vec![0xFF, 0xFE];
+    for unit in HTML_JAPANESE_PLAIN.encode_utf16() {
+        encoded.extend_from_slice(&unit.to_le_bytes());
+    }
+    let response = make_binary_response(Some("text/html; charset=shift_jis"), &encoded);
+    assert_case!(
+        response.text().as_deref(),
+        Some(HTML_JAPANESE_PLAIN),
+        "Decoding UTF-16LE body by BOM prior to Content-Type charset"
+    );
+
+    let response = make_binary_response(Some("text/html"), HTML_JAPANESE_PLAIN.as_bytes());
+    assert_case!(
+        response.text().as_deref(),
+        Some(HTML_JAPANESE_PLAIN),
+        "Decoding UTF-8 body without any charset"
+    );
+}
+
+#[test]
+fn skips_non_text_body() {
+    let response = make_binary_response(Some("image/png"), b"\x89PNG\r\n\x1a\n");
+    assert_case!(response.text(), None, "Decoding PNG body");
+
+    let response = make_binary_response(Some("application/json"), b"{}");
+    assert_case!(response.text().as_deref(), Some("{}"), "Decoding JSON body");
+}
+
+#[test]
+fn shares_decoded_content() {
+    let (encoded, _, _) = SHIFT_JIS.encode(HTML_JAPANESE_META_CHARSET);
+    let response = make_binary_response(Some("text/html"), &encoded);
+    let content = ResponseContent::of(&response);
+    assert_case!(
+        content.text(),
+        response.text().as_deref(),
+        "Content is decoded in the same way as the response"
+    );
+    assert_case!(
+        content
+            .document()
+            .tags
+            .iter()
+            .map(|t| &t.name[..])
+            .collect::<Vec<_>>(),
+        vec!["html", "head", "meta", "title"],
+        "Content is tokenized"
+    );
+
+    let content = ResponseContent::of(&make_binary_response(Some("image/png"), b"<html>"));
+    assert_case!(
+        (content.text(), content.document().tags.len()),
+        (None, 0),
+        "Binary content is neither decoded nor tokenized"
+    );
+}
+
+#[test]
+fn decodes_content_in_chunks() {
+    let cases = vec![
+        (
+            SHIFT_JIS.encode(HTML_JAPANESE_META_CHARSET).0,
+            "Shift_JIS by <meta charset>",
+        ),
+        (
+            EUC_JP.encode(HTML_JAPANESE_META_HTTP_EQUIV).0,
+            "EUC-JP by <meta http-equiv>",
+        ),
+        (HTML_JAPANESE_PLAIN.as_bytes().into(), "UTF-8 by default"),
+    ];
+    for (encoded, name) in cases {
+        let response = make_binary_response(Some("text/html"), &encoded);
+        let whole = ResponseContent::of(&response);
+
+        let mut content = ResponseContent::new(&response);
+        for byte in encoded.chunks(1) {
+            content.feed(byte);
+        }
+        content.finish();
+        assert_case!(
+            (content.text(), content.document()),
+            (whole.text(), whole.document()),
+            "{} in chunks is decoded as a whole",
+            name
+        );
+    }
+}
+
+#[async_test]
+async fn validates_decoded_body() {
+    let validator = ResponseHtmlValidator::new(Arc::new(TargetUrls::default()));
+
+    let html = format!(
+        "{}<meta http-equiv='refresh' content='0;https://twitter.com/mpyw'>",
+        HTML_JAPANESE_META_CHARSET
+    );
+    let (encoded, _, _) = SHIFT_JIS.encode(&html);
+    let status = make_binary_response(Some("text/html"), &encoded)
+        .validate(&validator)
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectContent),
+        "Validating Shift_JIS response by content"
+    );
+
+    let status = make_binary_response(Some("application/octet-stream"), html.as_bytes())
+        .validate(&validator)
+        .await;
+    assert_case!(status, None, "Validating binary response by content");
+}
+
+#[async_test]
+async fn decodes_non_ascii_header() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut response = b"HTTP/1.1 302 Found\r\nX-Name: \xe3\x83\x9b\xe3\x83\xa2\xff\r\n".to_vec();
+    response.extend_from_slice(b"Location: https://twitter.com/mpyw\r\nContent-Length: 0\r\n\r\n");
+    serve_raw(listener, response, Duration::from_millis(0));
+
+    let client = HttpClient::new(Duration::from_secs(1))
+        .unwrap()
+        .with_egress(EgressPolicy {
+            allowlist: vec!["127.0.0.1".parse().unwrap()],
+            ..Default::default()
+        });
+    let url = Url::parse(&format!("http://127.0.0.1:{}/", port)).unwrap();
+    let response = client.fetch(&url, None).await.unwrap();
+    assert_case!(
+        response.headers.get("x-name").map(String::as_str),
+        Some("ホモ\u{fffd}"),
+        "Non-ASCII header value is decoded lossily"
+    );
+    assert_case!(
+        response.headers.get("location").map(String::as_str),
+        Some("https://twitter.com/mpyw"),
+        "Other headers are kept"
+    );
+}
@@ -2,12 +2,22 @@ mod support;
 
 use self::support::{make_content_response, make_redirect_response};
 use homochecker_rs::{
-    domain::HomoServiceStatus,
-    validation::response::{ResponseHeaderValidator, ResponseHtmlValidator, ValidateResponseExt},
+    domain::{HomoServiceEvidence, HomoServiceStatus, HttpResponse},
+    validation::{
+        response::{
+            ResponseCanonicalValidator, ResponseHeaderValidator, ResponseHtmlValidator,
+            ResponseLinkValidator, ResponseMentionValidator, ResponseScriptValidator,
+            ValidateResponse, ValidateResponseExt, ValidatorPipeline, Verdict, DEFAULT_VALIDATORS,
+        },
+        Refresh, ResponseContent, TargetUrls,
+    },
 };
+use std::{sync::Arc, time::Duration};
 
+use async_trait::async_trait;
 use http::StatusCode;
 use tokio::test as async_test;
+use url::Url;
 
 const HTML_VALID_REDIRECT: &str = r#"
     <html>
@@ -19,7 +29,147 @@ const HTML_VALID_REDIRECT: &str = r#"
         </body>
     </html>
 "#;
+const HTML_VALID_REDIRECT_UPPERCASE: &str = r#"
+    <HTML>
+        <HEAD>
+            <META HTTP-EQUIV='Refresh' CONTENT=0;url=https://twitter.com/mpyw>
+        </HEAD>
+    </HTML>
+"#;
+const HTML_VALID_REDIRECT_MULTILINE: &str = r#"
+    <html>
+        <head>
+            <meta
+                content='0; URL=https://twitter.com/mpyw'
+                http-equiv = "refresh"
+            />
+        </head>
+    </html>
+"#;
+const HTML_COMMENTED_REDIRECT: &str = r#"
+    <html>
+        <head>
+            <!-- <meta http-equiv="refresh" content="0;https://twitter.com/mpyw"> -->
+        </head>
+    </html>
+"#;
+const HTML_SCRIPT_REDIRECT_TEXT: &str = r#"
+    <html>
+        <head>
+            <script>
+                var meta = '<meta http-equiv="refresh" content="0;https://twitter.com/mpyw">';
+            </script>
+        </head>
+    </html>
+"#;
+const HTML_DUPLICATED_ATTRIBUTE: &str = r#"
+    <html>
+        <head>
+            <meta http-equiv="content-type" http-equiv="refresh" content="0;https://twitter.com/mpyw">
+        </head>
+    </html>
+"#;
+const HTML_VALID_SCRIPT_ASSIGN: &str = r#"
+    <html>
+        <head>
+            <script>window.location.href = "https:\/\/twitter.com\/mpyw";</script>
+        </head>
+    </html>
+"#;
+const HTML_VALID_SCRIPT_REPLACE: &str = r#"
+    <html>
+        <head>
+            <script type="text/javascript">
+                setTimeout(function () { location.replace('https://x.com/mpyw'); }, 0);
+            </script>
+        </head>
+    </html>
+"#;
+const HTML_VALID_SCRIPT_HANDLER: &str = r#"
+    <html>
+        <body onload="document.location = 'https://twitter.com/mpyw'">
+        </body>
+    </html>
+"#;
+const HTML_INVALID_SCRIPT: &str = r#"
+    <html>
+        <head>
+            <script>
+                if (location.href == "https://twitter.com/mpyw") {
+                    location.href = "https://twitter.com/kb10uy";
+                }
+            </script>
+        </head>
+    </html>
+"#;
+const HTML_VALID_CANONICAL: &str = r#"
+    <html>
+        <head>
+            <link rel="alternate canonical" href="https://twitter.com/mpyw">
+        </head>
+    </html>
+"#;
+const HTML_VALID_OGP: &str = r#"
+    <html>
+        <head>
+            <meta property="og:url" content="https://x.com/mpyw">
+        </head>
+    </html>
+"#;
+const HTML_INVALID_CANONICAL: &str = r#"
+    <html>
+        <head>
+            <link rel="canonical" href="/">
+            <meta property="og:title" content="https://twitter.com/mpyw">
+            <a rel="canonical" href="https://twitter.com/mpyw">@mpyw</a>
+        </head>
+    </html>
+"#;
 const HTML_VALID_CONTENT: &str = r#"
+    <html>
+        <head>
+            <title>@mpyw</title>
+        </head>
+        <body>
+            <a href="https://twitter.com/mpyw">https://twitter.com/mpyw</a>
+        </body>
+    </html>
+"#;
+const HTML_REPEATED_REDIRECT: &str = r#"
+    <html>
+        <head>
+            <meta name="description" content="0;https://twitter.com/mpyw">
+            <meta http-equiv="refresh" content='0;https://twitter.com/mpyw'>
+        </head>
+    </html>
+"#;
+const HTML_REPEATED_CONTENT: &str = r#"
+    <html>
+        <body>
+            <!-- <a href="https://twitter.com/mpyw"> -->
+            <a title="https://twitter.com/mpyw" href="https&#x3a;//twitter.com/mpyw">@mpyw</a>
+        </body>
+    </html>
+"#;
+const HTML_MIXED_SCRIPT: &str = r#"
+    <html>
+        <script>
+            location.replace("https://twitter.com/mpyw");
+            location.href = "https://twitter.com/mpyw";
+        </script>
+    </html>
+"#;
+const HTML_REPEATED_SCRIPT: &str = "<html>\r\n<!-- location.href = \"https://twitter.com/mpyw\" -->\r\n<script>\r\nvar a = 1;\r\nlocation.href = \"https://twitter.com/mpyw\";\r\n</script>\r\n</html>";
+const HTML_VALID_CONTENT_AREA: &str = r#"
+    <html>
+        <body>
+            <map name="homo">
+                <area shape="rect" coords="0,0,114,514" href="//twitter.com/mpyw/">
+            </map>
+        </body>
+    </html>
+"#;
+const HTML_MENTION_CONTENT: &str = r#"
     <html>
         <head>
             <title>@mpyw</title>
@@ -29,6 +179,18 @@ const HTML_VALID_CONTENT: &str = r#"
         </body>
     </html>
 "#;
+const HTML_MENTION_INCIDENTAL: &str = r#"
+    <html>
+        <head>
+            <!-- https://twitter.com/mpyw -->
+            <script>var url = "https://twitter.com/mpyw";</script>
+        </head>
+        <body>
+            <img src="homo.png" alt="https://twitter.com/mpyw">
+            <a href="https://twitter.com/kb10uy">https://twitter.com/mpyw</a>
+        </body>
+    </html>
+"#;
 const HTML_INVALID_CONTENT: &str = r#"
     <html>
         <head>
@@ -39,10 +201,165 @@ const HTML_INVALID_CONTENT: &str = r#"
     </html>
 "#;
 
+fn header_validator() -> ResponseHeaderValidator {
+    ResponseHeaderValidator::new(Arc::new(TargetUrls::default()))
+}
+
+fn html_validator() -> ResponseHtmlValidator {
+    ResponseHtmlValidator::new(Arc::new(TargetUrls::default()))
+}
+
+fn canonical_validator() -> ResponseCanonicalValidator {
+    ResponseCanonicalValidator::new(Arc::new(TargetUrls::default()))
+}
+
+fn link_validator() -> ResponseLinkValidator {
+    ResponseLinkValidator::new(Arc::new(TargetUrls::default()))
+}
+
+fn mention_validator() -> ResponseMentionValidator {
+    ResponseMentionValidator::new(Arc::new(TargetUrls::default()))
+}
+
+fn script_validator() -> ResponseScriptValidator {
+    ResponseScriptValidator::new(Arc::new(TargetUrls::default()))
+}
+
+/// Regards every `text/plain` response as a redirect.
+struct PlainTextValidator;
+
+#[async_trait]
+impl ValidateResponse for PlainTextValidator {
+    fn name(&self) -> &str {
+        "plain"
+    }
+
+    async fn validate(
+        &self,
+        response: &HttpResponse,
+        _content: &ResponseContent,
+    ) -> Option<Verdict> {
+        match response.mime_type().as_deref() {
+            Some("text/plain") => Some(Verdict::new(HomoServiceStatus::RedirectContent)),
+            _ => None,
+        }
+    }
+}
+
+#[test]
+fn matches_target_urls() {
+    let targets: TargetUrls = "x.com/mpyw, https://twitter.com/mpyw/".parse().unwrap();
+    let matches = |url: &str| targets.matches(&Url::parse(url).unwrap());
+
+    assert_case!(
+        matches("https://twitter.com/mpyw"),
+        true,
+        "Matching exact target URL"
+    );
+    assert_case!(
+        matches("https://x.com/mpyw"),
+        true,
+        "Matching target URL specified without scheme"
+    );
+    assert_case!(
+        matches("https://X.COM/mpyw/status/1?s=20#top"),
+        true,
+        "Matching target URL with trailing path and query string"
+    );
+    assert_case!(
+        matches("https://twitter.com/mpyw2"),
+        false,
+        "Matching another user with the same prefix"
+    );
+    assert_case!(
+        matches("http://twitter.com/mpyw"),
+        false,
+        "Matching target URL with another scheme"
+    );
+    assert_case!(
+        matches("https://mobile.twitter.com/mpyw"),
+        false,
+        "Matching URL not in the set"
+    );
+    assert_case!(
+        targets.find_in("See https://x.com/mpyw/likes."),
+        Some((4, "https://x.com/mpyw/likes")),
+        "Finding target URL in text"
+    );
+}
+
+#[test]
+fn parses_refresh_value() {
+    let cases = [
+        ("0", Some((0, None))),
+        (
+            "0;https://twitter.com/mpyw",
+            Some((0, Some("https://twitter.com/mpyw"))),
+        ),
+        (
+            "5; url=https://twitter.com/mpyw",
+            Some((5, Some("https://twitter.com/mpyw"))),
+        ),
+        (
+            " 0.5 , URL = 'https://twitter.com/mpyw' ",
+            Some((0, Some("https://twitter.com/mpyw"))),
+        ),
+        (
+            "3 url=\"https://twitter.com/mpyw\"trailing",
+            Some((3, Some("https://twitter.com/mpyw"))),
+        ),
+        (
+            ".5;url=https://twitter.com/mpyw",
+            Some((0, Some("https://twitter.com/mpyw"))),
+        ),
+        ("0;url", Some((0, Some("url")))),
+        ("0;", Some((0, None))),
+        ("now;url=https://twitter.com/mpyw", None),
+        ("0x;url=https://twitter.com/mpyw", None),
+    ];
+    for (value, expected) in cases.iter() {
+        let expected = expected.map(|(delay, url)| Refresh {
+            delay: Duration::from_secs(delay),
+            url,
+        });
+        assert_case!(
+            Refresh::parse(value),
+            expected,
+            "Parsing refresh value `{}`",
+            value
+        );
+    }
+}
+
+#[async_test]
+async fn checks_response_header_refresh() {
+    let mut response = make_content_response("text/html", HTML_INVALID_CONTENT);
+    response
+        .headers
+        .insert("refresh".into(), "0; url='https://twitter.com/mpyw'".into());
+    let status = response.validate(&header_validator()).await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectContent),
+        "Validating response by header with Refresh header"
+    );
+
+    let mut response = make_content_response("text/html", HTML_INVALID_CONTENT);
+    response
+        .headers
+        .insert("refresh".into(), "0; url=https://twitter.com/kb10uy".into());
+    let status = response.validate(&header_validator()).await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by header with Refresh header to wrong URL"
+    );
+}
+
 #[async_test]
 async fn checks_response_header_ok() {
     let status = make_redirect_response(StatusCode::MOVED_PERMANENTLY, "https://twitter.com/mpyw")
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
@@ -51,7 +368,7 @@ async fn checks_response_header_ok() {
     );
 
     let status = make_redirect_response(StatusCode::FOUND, "https://twitter.com/mpyw")
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
@@ -60,7 +377,7 @@ async fn checks_response_header_ok() {
     );
 
     let status = make_redirect_response(StatusCode::SEE_OTHER, "https://twitter.com/mpyw")
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
@@ -69,7 +386,7 @@ async fn checks_response_header_ok() {
     );
 
     let status = make_redirect_response(StatusCode::PERMANENT_REDIRECT, "https://twitter.com/mpyw")
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
@@ -78,20 +395,77 @@ async fn checks_response_header_ok() {
     );
 
     let status = make_redirect_response(StatusCode::TEMPORARY_REDIRECT, "https://twitter.com/mpyw")
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
         Some(HomoServiceStatus::RedirectResponse),
         "Validating response by header with 308 response"
     );
+
+    let status = make_redirect_response(StatusCode::FOUND, "https://x.com/mpyw?s=20")
+        .validate(&header_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectResponse),
+        "Validating response by header with redirect to new domain"
+    );
+
+    let status = make_redirect_response(StatusCode::FOUND, "https://mobile.twitter.com/mpyw/")
+        .validate(&header_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectResponse),
+        "Validating response by header with redirect to mobile site"
+    );
+}
+
+#[async_test]
+async fn checks_response_header_relative() {
+    let status = make_redirect_response(StatusCode::FOUND, "//twitter.com/mpyw")
+        .validate(&header_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectResponse),
+        "Validating response by header with protocol-relative Location"
+    );
+
+    let status = make_redirect_response(StatusCode::FOUND, "https://Twitter.COM/mpyw")
+        .validate(&header_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectResponse),
+        "Validating response by header with different host casing"
+    );
+
+    let mut response = make_redirect_response(StatusCode::FOUND, "../mpyw");
+    response.url = Url::parse("https://twitter.com/intent/user").unwrap();
+    let status = response.validate(&header_validator()).await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectResponse),
+        "Validating response by header with relative Location"
+    );
+
+    let status = make_redirect_response(StatusCode::FOUND, "/mpyw")
+        .validate(&header_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::Invalid),
+        "Validating response by header with relative Location to the service itself"
+    );
 }
 
 #[async_test]
 async fn checks_response_header_invalid() {
     let status =
         make_redirect_response(StatusCode::MOVED_PERMANENTLY, "https://twitter.com/kb10uy")
-            .validate::<ResponseHeaderValidator>()
+            .validate(&header_validator())
             .await;
     assert_case!(
         status,
@@ -100,7 +474,7 @@ async fn checks_response_header_invalid() {
     );
 
     let status = make_redirect_response(StatusCode::FOUND, "https://twitter.com/kb10uy")
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
@@ -109,7 +483,7 @@ async fn checks_response_header_invalid() {
     );
 
     let status = make_redirect_response(StatusCode::SEE_OTHER, "https://twitter.com/kb10uy")
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
@@ -119,7 +493,7 @@ async fn checks_response_header_invalid() {
 
     let status =
         make_redirect_response(StatusCode::PERMANENT_REDIRECT, "https://twitter.com/kb10uy")
-            .validate::<ResponseHeaderValidator>()
+            .validate(&header_validator())
             .await;
     assert_case!(
         status,
@@ -129,19 +503,28 @@ async fn checks_response_header_invalid() {
 
     let status =
         make_redirect_response(StatusCode::TEMPORARY_REDIRECT, "https://twitter.com/kb10uy")
-            .validate::<ResponseHeaderValidator>()
+            .validate(&header_validator())
             .await;
     assert_case!(
         status,
         Some(HomoServiceStatus::Invalid),
         "Validating response by header with 308 wrong redirect"
     );
+
+    let status = make_redirect_response(StatusCode::FOUND, "https://x.com/mpyw2")
+        .validate(&header_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::Invalid),
+        "Validating response by header with redirect to similar screen name"
+    );
 }
 
 #[async_test]
 async fn checks_response_header_unknown() {
     let status = make_content_response("text/html", HTML_VALID_REDIRECT)
-        .validate::<ResponseHeaderValidator>()
+        .validate(&header_validator())
         .await;
     assert_case!(
         status,
@@ -153,7 +536,7 @@ async fn checks_response_header_unknown() {
 #[async_test]
 async fn checks_response_content_equiv() {
     let status = make_content_response("text/html", HTML_VALID_REDIRECT)
-        .validate::<ResponseHtmlValidator>()
+        .validate(&html_validator())
         .await;
     assert_case!(
         status,
@@ -162,22 +545,121 @@ async fn checks_response_content_equiv() {
     );
 }
 
+#[async_test]
+async fn checks_response_content_equiv_variants() {
+    let status = make_content_response("text/html", HTML_VALID_REDIRECT_UPPERCASE)
+        .validate(&html_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectContent),
+        "Validating response by content with uppercase and unquoted attributes"
+    );
+
+    let status = make_content_response("text/html", HTML_VALID_REDIRECT_MULTILINE)
+        .validate(&html_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectContent),
+        "Validating response by content with meta tag split across lines"
+    );
+}
+
+#[async_test]
+async fn checks_response_content_equiv_ignored() {
+    let status = make_content_response("text/html", HTML_COMMENTED_REDIRECT)
+        .validate(&html_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by content with commented out meta tag"
+    );
+
+    let status = make_content_response("text/html", HTML_SCRIPT_REDIRECT_TEXT)
+        .validate(&html_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by content with meta tag in script string"
+    );
+
+    let status = make_content_response("text/html", HTML_DUPLICATED_ATTRIBUTE)
+        .validate(&html_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by content with duplicated http-equiv attribute"
+    );
+}
+
 #[async_test]
 async fn checks_response_content_contains() {
     let status = make_content_response("text/html", HTML_VALID_CONTENT)
-        .validate::<ResponseHtmlValidator>()
+        .validate(&link_validator())
         .await;
     assert_case!(
         status,
         Some(HomoServiceStatus::LinkContent),
         "Validating response by content with HTML response which have link"
     );
+
+    let status = make_content_response("text/html", HTML_VALID_CONTENT_AREA)
+        .validate(&link_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::LinkContent),
+        "Validating response by content with HTML response which have area link"
+    );
+
+    let status = make_content_response("text/html", HTML_MENTION_INCIDENTAL)
+        .validate(&link_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by content with HTML response which just mentions URL"
+    );
+}
+
+#[async_test]
+async fn checks_response_content_mention() {
+    let status = make_content_response("text/html", HTML_MENTION_CONTENT)
+        .validate(&mention_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::MentionOnly),
+        "Validating response by mention with URL in text"
+    );
+
+    let status = make_content_response("text/html", HTML_MENTION_INCIDENTAL)
+        .validate(&mention_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::MentionOnly),
+        "Validating response by mention with URL in comment, script and alt text"
+    );
+
+    let status = make_content_response("text/html", HTML_INVALID_CONTENT)
+        .validate(&mention_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by mention with invalid HTML response"
+    );
 }
 
 #[async_test]
 async fn checks_response_content_err() {
     let status = make_content_response("text/html", HTML_INVALID_CONTENT)
-        .validate::<ResponseHtmlValidator>()
+        .validate(&html_validator())
         .await;
     assert_case!(
         status,
@@ -185,3 +667,271 @@ async fn checks_response_content_err() {
         "Validating response by content with invalid HTML response"
     );
 }
+
+#[async_test]
+async fn checks_response_script_redirect() {
+    let status = make_content_response("text/html", HTML_VALID_SCRIPT_ASSIGN)
+        .validate(&script_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectScript),
+        "Validating response by script with location assignment"
+    );
+
+    let status = make_content_response("text/html", HTML_VALID_SCRIPT_REPLACE)
+        .validate(&script_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectScript),
+        "Validating response by script with location.replace()"
+    );
+
+    let status = make_content_response("text/html", HTML_VALID_SCRIPT_HANDLER)
+        .validate(&script_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::RedirectScript),
+        "Validating response by script with event handler attribute"
+    );
+}
+
+#[async_test]
+async fn checks_response_script_invalid() {
+    let status = make_content_response("text/html", HTML_INVALID_SCRIPT)
+        .validate(&script_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by script with comparison and wrong redirect"
+    );
+
+    let status = make_content_response("text/html", HTML_VALID_CONTENT)
+        .validate(&script_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by script with HTML response without script"
+    );
+}
+
+#[async_test]
+async fn validates_by_pipeline() {
+    let targets = Arc::new(TargetUrls::default());
+    let pipeline =
+        ValidatorPipeline::from_names(DEFAULT_VALIDATORS.iter().copied(), targets.clone()).unwrap();
+
+    let status = pipeline
+        .validate(&make_redirect_response(
+            StatusCode::FOUND,
+            "https://twitter.com/mpyw",
+        ))
+        .await;
+    assert_case!(
+        status,
+        HomoServiceStatus::RedirectResponse,
+        "Validating redirect response by default pipeline"
+    );
+
+    let status = pipeline
+        .validate(&make_content_response("text/html", HTML_VALID_REDIRECT))
+        .await;
+    assert_case!(
+        status,
+        HomoServiceStatus::RedirectContent,
+        "Validating redirecting HTML response by default pipeline"
+    );
+
+    let status = pipeline
+        .validate(&make_content_response("text/html", HTML_VALID_CONTENT))
+        .await;
+    assert_case!(
+        status,
+        HomoServiceStatus::LinkContent,
+        "Validating HTML response which have link by default pipeline"
+    );
+
+    let status = pipeline
+        .validate(&make_content_response("text/html", HTML_MENTION_INCIDENTAL))
+        .await;
+    assert_case!(
+        status,
+        HomoServiceStatus::MentionOnly,
+        "Validating HTML response which just mentions URL by default pipeline"
+    );
+
+    let status = pipeline
+        .validate(&make_content_response("text/html", HTML_INVALID_CONTENT))
+        .await;
+    assert_case!(
+        status,
+        HomoServiceStatus::Invalid,
+        "Validating invalid HTML response by default pipeline"
+    );
+}
+
+#[async_test]
+async fn validates_by_configured_pipeline() {
+    let targets = Arc::new(TargetUrls::default());
+    let strict = ValidatorPipeline::from_names("header, html".split(','), targets.clone()).unwrap();
+    assert_case!(
+        strict.names(),
+        vec!["header", "html"],
+        "Constructing pipeline from names"
+    );
+
+    let status = strict
+        .validate(&make_content_response("text/html", HTML_VALID_CONTENT))
+        .await;
+    assert_case!(
+        status,
+        HomoServiceStatus::Invalid,
+        "Validating HTML response which have link without link validator"
+    );
+
+    let custom = strict.with(PlainTextValidator);
+    let status = custom
+        .validate(&make_content_response("text/plain", "homo"))
+        .await;
+    assert_case!(
+        status,
+        HomoServiceStatus::RedirectContent,
+        "Validating response by user-defined validator"
+    );
+
+    let unknown = ValidatorPipeline::from_names(vec!["header", "ogp"], targets);
+    assert_case!(
+        unknown.is_err(),
+        true,
+        "Constructing pipeline from unknown name"
+    );
+}
+
+#[async_test]
+async fn attaches_evidence() {
+    let pipeline = ValidatorPipeline::from_names(
+        DEFAULT_VALIDATORS.iter().copied(),
+        Arc::new(TargetUrls::default()),
+    )
+    .unwrap();
+
+    let response = make_content_response("text/html", HTML_VALID_REDIRECT);
+    let offset = HTML_VALID_REDIRECT.find("0;https").unwrap();
+    assert_case!(
+        pipeline.judge(&response).await,
+        (
+            HomoServiceStatus::RedirectContent,
+            Some(HomoServiceEvidence {
+                validator: "html".into(),
+                matched: Some("0;https://twitter.com/mpyw".into()),
+                offset: Some(offset),
+            })
+        ),
+        "Evidence of redirecting HTML response"
+    );
+
+    let response = make_content_response("text/html", HTML_VALID_CONTENT);
+    let offset = HTML_VALID_CONTENT.find("https").unwrap();
+    assert_case!(
+        pipeline.judge(&response).await,
+        (
+            HomoServiceStatus::LinkContent,
+            Some(HomoServiceEvidence {
+                validator: "link".into(),
+                matched: Some("https://twitter.com/mpyw".into()),
+                offset: Some(offset),
+            })
+        ),
+        "Evidence of HTML response which have link"
+    );
+
+    let response = make_content_response("text/html", HTML_INVALID_CONTENT);
+    assert_case!(
+        pipeline.judge(&response).await,
+        (HomoServiceStatus::Invalid, None),
+        "Evidence of invalid HTML response"
+    );
+}
+
+#[async_test]
+async fn checks_response_canonical() {
+    let status = make_content_response("text/html", HTML_VALID_CANONICAL)
+        .validate(&canonical_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::CanonicalContent),
+        "Validating response by canonical link"
+    );
+
+    let status = make_content_response("text/html", HTML_VALID_OGP)
+        .validate(&canonical_validator())
+        .await;
+    assert_case!(
+        status,
+        Some(HomoServiceStatus::CanonicalContent),
+        "Validating response by og:url"
+    );
+
+    let status = make_content_response("text/html", HTML_INVALID_CANONICAL)
+        .validate(&canonical_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by canonical link to itself"
+    );
+
+    let status = make_content_response("text/html", HTML_VALID_CONTENT)
+        .validate(&canonical_validator())
+        .await;
+    assert_case!(
+        status,
+        None,
+        "Validating response by canonical link with HTML response which have link"
+    );
+}
+
+#[async_test]
+async fn locates_evidence() {
+    let pipeline = ValidatorPipeline::from_names(
+        DEFAULT_VALIDATORS.iter().copied(),
+        Arc::new(TargetUrls::default()),
+    )
+    .unwrap();
+    let cases = [
+        (
+            HTML_REPEATED_REDIRECT,
+            HTML_REPEATED_REDIRECT.rfind("0;https"),
+            "Meta element after another one with the same content",
+        ),
+        (
+            HTML_REPEATED_CONTENT,
+            HTML_REPEATED_CONTENT.find("https&#x3a;"),
+            "Entity-encoded link after the target in comment and attribute",
+        ),
+        (
+            HTML_REPEATED_SCRIPT,
+            HTML_REPEATED_SCRIPT.rfind("location.href"),
+            "Script after the same expression in comment",
+        ),
+        (
+            HTML_MIXED_SCRIPT,
+            HTML_MIXED_SCRIPT.find("location.replace"),
+            "Call before assignment in script",
+        ),
+    ];
+    for (html, expected, message) in &cases {
+        let response = make_content_response("text/html", html);
+        let offset = pipeline
+            .judge(&response)
+            .await
+            .1
+            .and_then(|evidence| evidence.offset);
+        assert_case!(offset, *expected, "{}", message);
+    }
+}
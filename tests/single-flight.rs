@@ -0,0 +1,88 @@
+mod support;
+
+use homochecker_rs::service::SingleFlight;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use futures::future::{join, join3};
+use tokio::{
+    test as async_test,
+    time::{delay_for, timeout},
+};
+
+#[async_test]
+async fn coalesces_concurrent_calls() {
+    let flights = Arc::new(SingleFlight::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+    let call = |key: &'static str| {
+        let flights = flights.clone();
+        let calls = calls.clone();
+        async move {
+            flights
+                .run(key, || async move {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    delay_for(Duration::from_millis(50)).await;
+                    key.len()
+                })
+                .await
+        }
+    };
+
+    let (first, second, other) = join3(call("homo"), call("homo"), call("mpyw")).await;
+    assert_case!(
+        (first, second, other),
+        ((4, false), (4, true), (4, false)),
+        "Concurrent calls with the same key"
+    );
+    assert_case!(
+        (
+            calls.load(Ordering::SeqCst),
+            flights.started(),
+            flights.coalesced(),
+            flights.in_flight()
+        ),
+        (2, 2, 1, 0),
+        "Counters after concurrent calls"
+    );
+
+    let again = call("homo").await;
+    assert_case!(again, (4, false), "Call after the previous one finished");
+}
+
+#[async_test]
+async fn takes_over_cancelled_call() {
+    let flights = Arc::new(SingleFlight::new());
+
+    let leader = {
+        let flights = flights.clone();
+        async move {
+            flights
+                .run("homo", || async {
+                    delay_for(Duration::from_secs(10)).await;
+                    "leader"
+                })
+                .await
+        }
+    };
+    let follower = {
+        let flights = flights.clone();
+        async move {
+            delay_for(Duration::from_millis(10)).await;
+            flights.run("homo", || async { "follower" }).await
+        }
+    };
+
+    // leader をキャンセルすると follower が自分で呼び出す
+    let leader = timeout(Duration::from_millis(50), leader);
+    let (leader, follower) = join(leader, follower).await;
+    assert_case!(
+        (leader.is_err(), follower, flights.in_flight()),
+        (true, ("follower", false), 0),
+        "Call following the cancelled one"
+    );
+}
@@ -2,10 +2,15 @@ mod repository;
 mod service;
 
 use self::{
-    repository::{MockAvatarRepository, MockUserRepository},
+    repository::{
+        MockAvatarRepository, MockCheckHistoryRepository, MockCheckResultRepository,
+        MockUserRepository,
+    },
     service::{MockAvatarService, MockHomoRequestService},
 };
-use homochecker_rs::{repository::Repositories, service::Services, Container};
+use homochecker_rs::{
+    action::CheckFlights, config::Config, repository::Repositories, service::Services, Container,
+};
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
@@ -17,12 +22,16 @@ type Ambox<T> = Arc<Mutex<Box<T>>>;
 pub struct MockContainer {
     pub repositories: MockRepositories,
     pub services: MockServices,
+    pub config: Arc<Config>,
+    pub flights: Arc<CheckFlights>,
 }
 
 #[derive(Default, Clone)]
 pub struct MockRepositories {
     pub user: MockUserRepository,
     pub avatar: MockAvatarRepository,
+    pub check_result: MockCheckResultRepository,
+    pub check_history: MockCheckHistoryRepository,
 }
 
 #[derive(Default, Clone)]
@@ -42,11 +51,21 @@ impl Container for MockContainer {
     fn services(&self) -> MockServices {
         self.services.clone()
     }
+
+    fn config(&self) -> Arc<Config> {
+        self.config.clone()
+    }
+
+    fn flights(&self) -> Arc<CheckFlights> {
+        self.flights.clone()
+    }
 }
 
 impl Repositories for MockRepositories {
     type User = MockUserRepository;
     type Avatar = MockAvatarRepository;
+    type CheckResult = MockCheckResultRepository;
+    type CheckHistory = MockCheckHistoryRepository;
 
     fn user(&self) -> MockUserRepository {
         self.user.clone()
@@ -55,6 +74,14 @@ impl Repositories for MockRepositories {
     fn avatar(&self) -> MockAvatarRepository {
         self.avatar.clone()
     }
+
+    fn check_result(&self) -> MockCheckResultRepository {
+        self.check_result.clone()
+    }
+
+    fn check_history(&self) -> MockCheckHistoryRepository {
+        self.check_history.clone()
+    }
 }
 
 impl Services for MockServices {
@@ -1,11 +1,15 @@
 use super::Amx;
 use homochecker_rs::{
     domain::Provider,
-    repository::{AvatarRepository, RepositoryError, User, UserRepository},
+    repository::{
+        AvatarRepository, CachedCheckResult, CheckHistoryRepository, CheckRecord,
+        CheckResultRepository, CheckSummary, RepositoryError, User, UserRepository,
+    },
 };
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{cmp::Reverse, collections::HashMap, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use tokio::sync::Mutex;
 use url::Url;
 
@@ -67,11 +71,112 @@ impl AvatarRepository for MockAvatarRepository {
         provider: &Provider,
         url: &str,
         _age: Duration,
+    ) -> Result<(), RepositoryError> {
+        let url = Url::parse(url).map_err(|e| RepositoryError::InvalidData(e.into()))?;
+        self.source.lock().await.insert(provider.clone(), url);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockCheckResultRepository {
+    source: Amx<HashMap<Url, CachedCheckResult>>,
+}
+
+#[allow(dead_code)]
+impl MockCheckResultRepository {
+    pub fn source(&self) -> Amx<HashMap<Url, CachedCheckResult>> {
+        self.source.clone()
+    }
+}
+
+#[async_trait]
+impl CheckResultRepository for MockCheckResultRepository {
+    async fn get(&self, service_url: &Url) -> Result<Option<CachedCheckResult>, RepositoryError> {
+        Ok(self.source.lock().await.get(service_url).cloned())
+    }
+
+    async fn save_cache(
+        &self,
+        service_url: &Url,
+        result: &CachedCheckResult,
+        _age: Duration,
     ) -> Result<(), RepositoryError> {
         self.source
             .lock()
             .await
-            .insert(provider.clone(), Url::parse(url)?);
+            .insert(service_url.clone(), result.clone());
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct MockCheckHistoryRepository {
+    source: Amx<Vec<CheckRecord>>,
+    summaries: Amx<Vec<CheckSummary>>,
+    summarized: Amx<Vec<DateTime<Utc>>>,
+}
+
+#[allow(dead_code)]
+impl MockCheckHistoryRepository {
+    pub fn source(&self) -> Amx<Vec<CheckRecord>> {
+        self.source.clone()
+    }
+
+    /// Returns the summaries returned regardless of the window.
+    /// The aggregation itself is done by PostgreSQL, so it is not mocked.
+    pub fn summaries(&self) -> Amx<Vec<CheckSummary>> {
+        self.summaries.clone()
+    }
+
+    /// Returns the start of windows given to `summarize_by_urls`, in order.
+    pub fn summarized(&self) -> Amx<Vec<DateTime<Utc>>> {
+        self.summarized.clone()
+    }
+}
+
+#[async_trait]
+impl CheckHistoryRepository for MockCheckHistoryRepository {
+    async fn save(&self, record: &CheckRecord) -> Result<(), RepositoryError> {
+        self.source.lock().await.push(record.clone());
         Ok(())
     }
+
+    async fn count_by_urls(&self, service_urls: &[Url]) -> Result<usize, RepositoryError> {
+        let locked = self.source.lock().await;
+        Ok(locked
+            .iter()
+            .filter(|r| service_urls.contains(&r.service_url))
+            .count())
+    }
+
+    async fn fetch_by_urls(
+        &self,
+        service_urls: &[Url],
+        limit: usize,
+        offset: usize,
+    ) -> Result<Vec<CheckRecord>, RepositoryError> {
+        let locked = self.source.lock().await;
+        let mut records: Vec<_> = locked
+            .iter()
+            .filter(|r| service_urls.contains(&r.service_url))
+            .cloned()
+            .collect();
+        records.sort_by_key(|r| Reverse(r.checked_at));
+        Ok(records.into_iter().skip(offset).take(limit).collect())
+    }
+
+    async fn summarize_by_urls(
+        &self,
+        service_urls: &[Url],
+        since: DateTime<Utc>,
+    ) -> Result<Vec<CheckSummary>, RepositoryError> {
+        self.summarized.lock().await.push(since);
+        let locked = self.summaries.lock().await;
+        Ok(locked
+            .iter()
+            .filter(|s| service_urls.contains(&s.service_url))
+            .cloned()
+            .collect())
+    }
 }
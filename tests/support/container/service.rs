@@ -1,6 +1,6 @@
 use super::Ambox;
 use homochecker_rs::{
-    domain::HttpResponse,
+    domain::{HttpResponse, IpVersion},
     service::{AvatarService, HomoRequestService, ServiceError},
 };
 use std::{sync::Arc, time::Duration};
@@ -9,10 +9,16 @@ use async_trait::async_trait;
 use tokio::sync::Mutex;
 use url::Url;
 
+type TwitterSource = dyn Fn(&str) -> Result<HttpResponse, ServiceError> + Send + Sync;
+type MastodonSource = dyn Fn(&str, &str) -> Result<HttpResponse, ServiceError> + Send + Sync;
+type HomoRequestSource = dyn Fn() -> Result<(HttpResponse, Duration), ServiceError> + Send + Sync;
+type HomoRequestOverSource =
+    dyn Fn(IpVersion) -> Result<(HttpResponse, Duration), ServiceError> + Send + Sync;
+
 #[derive(Clone)]
 pub struct MockAvatarService {
-    for_twitter: Ambox<dyn Fn(&str) -> HttpResponse + Send + Sync>,
-    for_mastodon: Ambox<dyn Fn(&str, &str) -> HttpResponse + Send + Sync>,
+    for_twitter: Ambox<TwitterSource>,
+    for_mastodon: Ambox<MastodonSource>,
 }
 
 impl Default for MockAvatarService {
@@ -30,11 +36,11 @@ impl MockAvatarService {
         }
     }
 
-    pub fn for_twitter(&self) -> Ambox<dyn Fn(&str) -> HttpResponse + Send + Sync> {
+    pub fn for_twitter(&self) -> Ambox<TwitterSource> {
         self.for_twitter.clone()
     }
 
-    pub fn for_mastodon(&self) -> Ambox<dyn Fn(&str, &str) -> HttpResponse + Send + Sync> {
+    pub fn for_mastodon(&self) -> Ambox<MastodonSource> {
         self.for_mastodon.clone()
     }
 }
@@ -43,7 +49,7 @@ impl MockAvatarService {
 impl AvatarService for MockAvatarService {
     async fn fetch_twitter(&self, screen_name: &str) -> Result<HttpResponse, ServiceError> {
         let function = self.for_twitter.lock().await;
-        Ok(function(screen_name))
+        function(screen_name)
     }
 
     async fn fetch_mastodon(
@@ -52,13 +58,14 @@ impl AvatarService for MockAvatarService {
         domain: &str,
     ) -> Result<HttpResponse, ServiceError> {
         let function = self.for_mastodon.lock().await;
-        Ok(function(screen_name, domain))
+        function(screen_name, domain)
     }
 }
 
 #[derive(Clone)]
 pub struct MockHomoRequestService {
-    source: Ambox<dyn Fn() -> (HttpResponse, Duration) + Send + Sync>,
+    source: Ambox<HomoRequestSource>,
+    source_over: Ambox<HomoRequestOverSource>,
 }
 
 impl Default for MockHomoRequestService {
@@ -72,18 +79,34 @@ impl MockHomoRequestService {
     pub fn new() -> MockHomoRequestService {
         MockHomoRequestService {
             source: Arc::new(Mutex::new(Box::new(|| todo!()))),
+            source_over: Arc::new(Mutex::new(Box::new(|version| {
+                panic!("source_over is not mocked for {:?}", version)
+            }))),
         }
     }
 
-    pub fn source(&self) -> Ambox<dyn Fn() -> (HttpResponse, Duration) + Send + Sync> {
+    pub fn source(&self) -> Ambox<HomoRequestSource> {
         self.source.clone()
     }
+
+    pub fn source_over(&self) -> Ambox<HomoRequestOverSource> {
+        self.source_over.clone()
+    }
 }
 
 #[async_trait]
 impl HomoRequestService for MockHomoRequestService {
     async fn request(&self, _: &Url) -> Result<(HttpResponse, Duration), ServiceError> {
         let function = self.source.lock().await;
-        Ok(function())
+        function()
+    }
+
+    async fn request_over(
+        &self,
+        _: &Url,
+        version: IpVersion,
+    ) -> Result<(HttpResponse, Duration), ServiceError> {
+        let function = self.source_over.lock().await;
+        function(version)
     }
 }
@@ -1,11 +1,18 @@
 // これがテスト結果に出力されるのを防ぐためのサブモジュール
 
+#[allow(dead_code)]
 pub mod container;
 
 use homochecker_rs::domain::{HomoService, HttpResponse, Provider};
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    net::{SocketAddr, TcpListener as StdTcpListener, TcpStream as StdTcpStream},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use http::StatusCode;
+use tokio::{net::TcpListener, prelude::*, spawn, sync::Mutex, time::delay_for};
 use url::Url;
 
 /// Pretty-prints assertion case.
@@ -50,6 +57,7 @@ macro_rules! fixture_content {
 #[allow(dead_code)]
 pub fn make_redirect_response(status: StatusCode, location: &str) -> HttpResponse {
     HttpResponse {
+        url: Url::parse("https://example.com").unwrap(),
         status,
         headers: {
             let mut h = HashMap::new();
@@ -58,12 +66,16 @@ pub fn make_redirect_response(status: StatusCode, location: &str) -> HttpRespons
         },
         remote_address: None,
         body: Default::default(),
+        redirects: vec![],
+        timing: None,
+        judgment: None,
     }
 }
 
 #[allow(dead_code)]
 pub fn make_content_response(content_type: &str, body: &str) -> HttpResponse {
     HttpResponse {
+        url: Url::parse("https://example.com").unwrap(),
         status: StatusCode::OK,
         headers: {
             let mut h = HashMap::new();
@@ -71,7 +83,30 @@ pub fn make_content_response(content_type: &str, body: &str) -> HttpResponse {
             h
         },
         remote_address: None,
-        body: body.into(),
+        body: body.as_bytes().to_vec(),
+        redirects: vec![],
+        timing: None,
+        judgment: None,
+    }
+}
+
+#[allow(dead_code)]
+pub fn make_binary_response(content_type: Option<&str>, body: &[u8]) -> HttpResponse {
+    HttpResponse {
+        url: Url::parse("https://example.com").unwrap(),
+        status: StatusCode::OK,
+        headers: {
+            let mut h = HashMap::new();
+            if let Some(content_type) = content_type {
+                h.insert("content-type".into(), content_type.into());
+            }
+            h
+        },
+        remote_address: None,
+        body: body.to_vec(),
+        redirects: vec![],
+        timing: None,
+        judgment: None,
     }
 }
 
@@ -82,3 +117,69 @@ pub fn make_homo_service(provider: Provider) -> HomoService {
         service_url: Url::parse("https://example.com").unwrap(),
     }
 }
+
+/// Waits until check results cached or recorded in the background are saved in the source of
+/// the mock repository. Panics if they are not saved in a few seconds.
+#[allow(dead_code)]
+pub async fn wait_for_saving<T>(source: &Arc<Mutex<T>>, saved: impl Fn(&T) -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !saved(&*source.lock().await) {
+        if Instant::now() >= deadline {
+            panic!("Timed out waiting for saving in the background");
+        }
+        delay_for(Duration::from_millis(1)).await;
+    }
+}
+
+/// Responds with the plain text to every connection on the listener.
+#[allow(dead_code)]
+pub fn serve_text(listener: TcpListener, body: impl Into<String>) {
+    let body = body.into();
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    serve_raw(listener, response.into_bytes(), Duration::default());
+}
+
+/// Writes the raw response to every connection on the listener,
+/// and then keeps the connection open for `hold`.
+#[allow(dead_code)]
+pub fn serve_raw(mut listener: TcpListener, response: Vec<u8>, hold: Duration) {
+    spawn(async move {
+        loop {
+            let (mut stream, _) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(_) => return,
+            };
+            let response = response.clone();
+            spawn(async move {
+                // リクエストヘッダーを読み捨てる
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => request.extend_from_slice(&buffer[..n]),
+                    }
+                }
+
+                stream.write_all(&response).await.ok();
+                delay_for(hold).await;
+            });
+        }
+    });
+}
+
+/// Listens on the address without accepting, and fills the backlog
+/// so that further connections hang like an unreachable host.
+#[allow(dead_code)]
+pub fn blackhole(listener: StdTcpListener) -> (StdTcpListener, Vec<StdTcpStream>) {
+    let address: SocketAddr = listener.local_addr().unwrap();
+    let mut streams = vec![];
+    while let Ok(stream) = StdTcpStream::connect_timeout(&address, Duration::from_millis(100)) {
+        streams.push(stream);
+    }
+    (listener, streams)
+}